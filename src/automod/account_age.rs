@@ -0,0 +1,106 @@
+use std::borrow::Cow;
+
+use bm_lib::{
+    discord::{DiscordResult, Id, User},
+    model::{automod::AccountAgeAction, logging::LogEvent, Config},
+    util,
+};
+use tracing::instrument;
+
+use crate::handler::EventHandler;
+
+impl EventHandler {
+    /// Enforce the minimum account age on a joining member. Account age comes
+    /// from the snowflake timestamp of the user ID.
+    ///
+    /// Kicked members get a DM explaining when they can join; quarantined
+    /// members get the explanation through the infraction DM instead. Returns
+    /// whether the member was removed or quarantined.
+    #[instrument(skip(self, config, user), fields(user_id = %user.id))]
+    pub async fn handle_account_age(
+        &self,
+        config: &Config,
+        guild_id: &Id,
+        user: &User,
+    ) -> DiscordResult<bool> {
+        let Some(gate) = config
+            .automod
+            .as_ref()
+            .and_then(|automod| automod.account_age.as_ref())
+            .filter(|gate| gate.enabled)
+        else {
+            return Ok(false);
+        };
+
+        if gate.allowlist.contains(&user.id) {
+            return Ok(false);
+        }
+
+        let created_at = util::snowflake_to_timestamp(user.id) as i64;
+        let account_age = chrono::Utc::now().timestamp_millis() - created_at;
+        if account_age >= gate.min_age as i64 {
+            return Ok(false);
+        }
+
+        let eligible_at = created_at + gate.min_age as i64;
+        let reason = format!(
+            "Account younger than {}, can join <t:{}:R>",
+            util::format_duration(gate.min_age / 1000),
+            eligible_at / 1000
+        );
+
+        let acted = match gate.action {
+            AccountAgeAction::LogOnly => false,
+            AccountAgeAction::Kick => {
+                // DM first, the bot can no longer reach them once they are gone
+                if let Err(e) = self
+                    .send_account_age_dm(
+                        guild_id,
+                        &user.id,
+                        eligible_at,
+                        gate.dm_message.as_deref(),
+                    )
+                    .await
+                {
+                    tracing::debug!(error = ?e, "failed to send account age DM");
+                }
+
+                self.rest
+                    .kick_member(guild_id, &user.id, Some(Cow::Borrowed(reason.as_str())))
+                    .await?;
+                true
+            }
+            AccountAgeAction::Quarantine => {
+                let (Some(bot_id), Some(quarantine_role)) =
+                    (self.bot_id.get(), config.quarantine_role.as_ref())
+                else {
+                    tracing::warn!("Account age quarantine configured without a quarantine role");
+                    return Ok(false);
+                };
+
+                self.quarantine_user(
+                    guild_id,
+                    &user.id,
+                    bot_id,
+                    quarantine_role,
+                    None,
+                    Some(Cow::Borrowed(reason.as_str())),
+                )
+                .await?;
+                true
+            }
+        };
+
+        let _ = self
+            .log_event(LogEvent::AccountAgeGate {
+                guild_id: *guild_id,
+                user_id: user.id,
+                username: user.username.to_string(),
+                account_age: account_age.max(0) as u64,
+                action: gate.action.to_string(),
+            })
+            .await;
+
+        Ok(acted)
+    }
+}
@@ -0,0 +1,145 @@
+use bm_lib::{discord::Attachment, model::automod::Censor};
+
+use super::censor::Pattern;
+
+const EXECUTABLE_EXTENSIONS: &[&str] = &[
+    "exe", "msi", "bat", "cmd", "com", "scr", "pif", "cpl", "hta", "dll", "lnk", "reg", "ps1",
+    "vbs", "vbe", "jse", "wsf", "jar", "apk", "app", "dmg", "pkg", "deb", "rpm", "sh",
+];
+
+const EXECUTABLE_MIME_TYPES: &[&str] = &[
+    "application/x-msdownload",
+    "application/x-msdos-program",
+    "application/x-msi",
+    "application/x-executable",
+    "application/x-sh",
+    "application/java-archive",
+    "application/vnd.android.package-archive",
+];
+
+const ARCHIVE_EXTENSIONS: &[&str] = &[
+    "zip", "rar", "7z", "tar", "gz", "tgz", "bz2", "xz", "zst", "cab", "iso",
+];
+
+const ARCHIVE_MIME_TYPES: &[&str] = &[
+    "application/zip",
+    "application/x-zip-compressed",
+    "application/vnd.rar",
+    "application/x-rar-compressed",
+    "application/x-7z-compressed",
+    "application/x-tar",
+    "application/gzip",
+    "application/x-bzip2",
+    "application/x-xz",
+    "application/x-iso9660-image",
+];
+
+/// Lowercased extension of a filename, without the leading dot.
+#[inline]
+fn extension(filename: &str) -> Option<String> {
+    filename
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_lowercase())
+        .filter(|ext| !ext.is_empty())
+}
+
+/// Lowercased MIME type with any parameters (`; charset=...`) stripped.
+#[inline]
+fn mime_type(attachment: &Attachment) -> Option<String> {
+    attachment
+        .content_type
+        .as_deref()
+        .map(|ct| ct.split(';').next().unwrap_or(ct).trim().to_lowercase())
+}
+
+/// Whether a single filter entry matches an attachment.
+///
+/// Entries containing a `/` are MIME types and may end in `/*` to match a
+/// whole family (`image/*`). Anything else is an extension, with or without
+/// the leading dot.
+fn filter_matches(filter: &str, ext: Option<&str>, mime: Option<&str>) -> bool {
+    let filter = filter.trim().to_lowercase();
+
+    if filter.contains('/') {
+        let Some(mime) = mime else {
+            return false;
+        };
+        match filter.strip_suffix("/*") {
+            Some(family) => mime.split('/').next() == Some(family),
+            None => mime == filter,
+        }
+    } else {
+        ext == Some(filter.trim_start_matches('.'))
+    }
+}
+
+/// Check a message's attachments against an attachment censor.
+///
+/// Returns a description of the first offending attachment, or `None` if all
+/// attachments are allowed. `filters` follow the usual censor semantics: with
+/// `whitelist` set every attachment must match an entry, otherwise any match
+/// is a violation. Size, executable and archive checks apply in both modes.
+/// `word_patterns` are the guild's word filters, checked against filenames
+/// when `match_filenames` is enabled.
+pub(super) fn attachment_violation(
+    censor: &Censor,
+    attachments: &[Attachment],
+    word_patterns: &[Pattern],
+) -> Option<String> {
+    attachments.iter().find_map(|attachment| {
+        let ext = extension(&attachment.filename);
+        let mime = mime_type(attachment);
+        let (ext, mime) = (ext.as_deref(), mime.as_deref());
+
+        if let Some(max) = censor.max_file_size {
+            if attachment.size > max {
+                return Some(format!(
+                    "{} ({} bytes)",
+                    attachment.filename, attachment.size
+                ));
+            }
+        }
+
+        if censor.block_executables
+            && (ext.is_some_and(|e| EXECUTABLE_EXTENSIONS.contains(&e))
+                || mime.is_some_and(|m| EXECUTABLE_MIME_TYPES.contains(&m)))
+        {
+            return Some(attachment.filename.clone());
+        }
+
+        if censor.block_archives
+            && (ext.is_some_and(|e| ARCHIVE_EXTENSIONS.contains(&e))
+                || mime.is_some_and(|m| ARCHIVE_MIME_TYPES.contains(&m)))
+        {
+            return Some(attachment.filename.clone());
+        }
+
+        if !censor.filters.is_empty() {
+            let listed = censor
+                .filters
+                .iter()
+                .any(|filter| filter_matches(filter, ext, mime));
+
+            if listed != censor.whitelist {
+                return Some(attachment.filename.clone());
+            }
+        }
+
+        if censor.match_filenames {
+            let filename = attachment.filename.to_lowercase();
+            let mut parts = filename
+                .split(|c: char| !c.is_alphanumeric())
+                .filter(|part| !part.is_empty());
+
+            if word_patterns
+                .iter()
+                .any(|pattern| pattern.matches(&filename))
+                || parts.any(|part| word_patterns.iter().any(|pattern| pattern.matches(part)))
+            {
+                return Some(attachment.filename.clone());
+            }
+        }
+
+        None
+    })
+}
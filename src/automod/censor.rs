@@ -1,17 +1,88 @@
+use std::borrow::Cow;
+
 use crate::handler::EventHandler;
 use bm_lib::{
-    discord::{commands::Ctx, DiscordResult},
+    discord::{commands::Ctx, Attachment, DiscordResult, Id},
     model::{
         automod::{AutomodOffense, Censor, CensorType, OffenseType},
-        Infraction,
+        Infraction, InfractionType,
     },
 };
 use tracing::instrument;
 
-use super::AutomodResult;
+use super::{
+    attachment::attachment_violation,
+    links::{domain_matches, extract_invite_codes, extract_links, normalized_host},
+    AutomodResult,
+};
+
+/// Action ladder the phishing censor can opt into when it has no escalation
+/// of its own. Scam links mostly come from compromised accounts, so repeats
+/// go straight to a kick and then a ban, on top of the censor's base action.
+const PHISHING_LADDER: &[(u64, InfractionType)] =
+    &[(2, InfractionType::Kick), (3, InfractionType::Ban)];
+
+/// Escalation window for censors that set none. Counting over a zero window
+/// would drop every hit as soon as it was added and never escalate.
+const DEFAULT_ESCALATION_WINDOW_MS: u64 = 24 * 60 * 60 * 1000;
+
+#[inline]
+fn censor_violations_key(guild_id: &Id, user_id: &Id, typ: &CensorType) -> String {
+    format!("censor_violations:{guild_id}:{user_id}:{typ:?}")
+}
+
+#[inline]
+fn uses_phishing_ladder(typ: &CensorType, censor: &Censor) -> bool {
+    *typ == CensorType::Phishing && censor.phishing_ladder && censor.escalation.is_empty()
+}
+
+/// Window violations are counted over, `None` when the censor doesn't
+/// escalate and never counts them.
+pub(super) fn escalation_window(typ: &CensorType, censor: &Censor) -> Option<u64> {
+    if censor.log_only || (censor.escalation.is_empty() && !uses_phishing_ladder(typ, censor)) {
+        return None;
+    }
+
+    Some(match censor.escalation_window {
+        0 => DEFAULT_ESCALATION_WINDOW_MS,
+        window => window,
+    })
+}
+
+/// Action and duration for a hit that brings the member to `violations`.
+///
+/// Same threshold semantics as spam: the highest rung reached wins, below the
+/// first rung the censor's base action applies.
+pub(super) fn censor_action(
+    typ: &CensorType,
+    censor: &Censor,
+    violations: Option<u64>,
+) -> (InfractionType, i64) {
+    let base = (censor.action.action.clone(), censor.action.duration);
+    let Some(violations) = violations else {
+        return base;
+    };
+
+    let rung = if uses_phishing_ladder(typ, censor) {
+        PHISHING_LADDER
+            .iter()
+            .filter(|(threshold, _)| violations >= *threshold)
+            .max_by_key(|(threshold, _)| *threshold)
+            .map(|(_, action)| (action.clone(), 0))
+    } else {
+        censor
+            .escalation
+            .iter()
+            .filter(|a| violations >= a.threshold)
+            .max_by_key(|a| a.threshold)
+            .map(|rung| (rung.action.clone(), rung.duration))
+    };
+
+    rung.unwrap_or(base)
+}
 
 #[derive(Clone)]
-enum Pattern {
+pub(super) enum Pattern {
     Simple(Box<str>),
     Wildcard {
         start: Box<str>,
@@ -22,7 +93,7 @@ enum Pattern {
 
 impl Pattern {
     #[inline]
-    fn matches(&self, text: &str) -> bool {
+    pub(super) fn matches(&self, text: &str) -> bool {
         match self {
             Pattern::Simple(pattern) => text.contains(pattern.as_ref()),
             Pattern::Wildcard {
@@ -48,7 +119,7 @@ impl Pattern {
     }
 
     #[inline]
-    fn from_str(pattern: &str) -> Self {
+    pub(super) fn from_str(pattern: &str) -> Self {
         if pattern.contains("...") {
             let parts: Vec<&str> = pattern.split("...").collect();
             if parts.len() == 2 {
@@ -73,129 +144,266 @@ impl Pattern {
     }
 }
 
-impl EventHandler {
-    #[instrument(skip(self, ctx), fields(guild_id = %ctx.guild_id, user_id = %ctx.user.id, message_id = %ctx.message.id, channel_id = %ctx.channel_id))]
-    pub(crate) async fn handle_censor(
-        &self,
-        ctx: &Ctx<'_>,
-        typ: &CensorType,
-        censor: &Censor,
-    ) -> DiscordResult<Option<AutomodResult>> {
-        if !censor.enabled || censor.filters.is_empty() {
-            return Ok(None);
-        }
+/// Content as a text censor sees it, with whitespace removed when the censor
+/// ignores it.
+pub(super) fn censor_content<'a>(message_content: &'a str, censor: &Censor) -> Cow<'a, str> {
+    if censor.ignore_whitespace {
+        message_content
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>()
+            .into()
+    } else {
+        message_content.into()
+    }
+}
 
-        let content = if censor.ignore_whitespace {
-            ctx.message
-                .content
-                .chars()
-                .filter(|c| !c.is_whitespace())
-                .collect::<String>()
-                .to_lowercase()
-        } else {
-            ctx.message.content.to_lowercase()
-        };
+/// Run a text censor against message content.
+///
+/// Returns `Some(found_filter)` when the censor is violated, where
+/// `found_filter` is the matched content (absent for whitelist violations).
+/// Invite codes in `exempt_invites` are never flagged and count as allowed
+/// for an invite whitelist.
+pub(super) fn find_censored_content(
+    message_content: &str,
+    typ: &CensorType,
+    censor: &Censor,
+    exempt_invites: &[String],
+) -> Option<Option<String>> {
+    if censor.filters.is_empty() {
+        return None;
+    }
 
-        let found_filter = match typ {
-            CensorType::Word => {
-                // Early exit if content is empty
-                if content.is_empty() {
-                    return Ok(None);
-                }
+    let stripped = censor_content(message_content, censor);
+    let content = stripped.to_lowercase();
 
-                // Pre-compile patterns once
-                let patterns: Vec<Pattern> = censor
-                    .filters
-                    .iter()
-                    .map(|p| Pattern::from_str(p))
-                    .collect();
-
-                let matched_word = patterns.iter().enumerate().find_map(|(i, pattern)| {
-                    content
-                        .split_whitespace()
-                        .find(|word| pattern.matches(word))
-                        .map(|w| w.to_string())
-                        .or_else(|| {
-                            // pattern matched multi-word content (e.g. phrase pattern)
-                            if pattern.matches(&content) {
-                                Some(censor.filters[i].clone())
-                            } else {
-                                None
-                            }
-                        })
-                });
-
-                matched_word
+    let found_filter = match typ {
+        CensorType::Word => {
+            // Early exit if content is empty
+            if content.is_empty() {
+                return None;
             }
 
-            CensorType::Link => content.split_whitespace().find_map(|s| {
-                let candidate = if s.contains("://") {
-                    s.to_string()
-                } else {
-                    format!("https://{s}")
-                };
-                url::Url::parse(&candidate).ok().and_then(|url| {
-                    url.host_str().and_then(|domain| {
-                        if censor.filters.iter().any(|filter| filter == domain) {
-                            Some(domain.to_string())
+            // Pre-compile patterns once
+            let patterns: Vec<Pattern> = censor
+                .filters
+                .iter()
+                .map(|p| Pattern::from_str(p))
+                .collect();
+
+            let matched_word = patterns.iter().enumerate().find_map(|(i, pattern)| {
+                content
+                    .split_whitespace()
+                    .find(|word| pattern.matches(word))
+                    .map(|w| w.to_string())
+                    .or_else(|| {
+                        // pattern matched multi-word content (e.g. phrase pattern)
+                        if pattern.matches(&content) {
+                            Some(censor.filters[i].clone())
                         } else {
                             None
                         }
                     })
-                })
-            }),
+            });
 
-            CensorType::Invite => content.split_whitespace().find_map(|s| {
-                let candidate = if s.contains("://") {
-                    s.to_string()
-                } else {
-                    format!("https://{s}")
-                };
-                url::Url::parse(&candidate).ok().and_then(|url| {
-                    if url.host_str() == Some("discord.gg") {
-                        url.path_segments()
-                            .and_then(|segments| segments.last())
-                            .and_then(|invite| {
-                                if censor.filters.iter().any(|filter| filter == invite) {
-                                    Some(invite.to_string())
-                                } else {
-                                    None
-                                }
-                            })
-                    } else {
-                        None
-                    }
-                })
+            matched_word
+        }
+
+        CensorType::Link => extract_links(&content)
+            .iter()
+            .filter_map(normalized_host)
+            .find(|host| {
+                censor
+                    .filters
+                    .iter()
+                    .any(|filter| domain_matches(filter, host))
             }),
+
+        // Invite codes are case-sensitive, so match against the original text.
+        // Exempt invites are skipped by a blacklist and allowed by a whitelist.
+        CensorType::Invite => extract_invite_codes(&stripped).into_iter().find(|code| {
+            let exempt = exempt_invites.contains(code);
+            let listed = censor
+                .filters
+                .iter()
+                .any(|filter| filter.eq_ignore_ascii_case(code));
+
+            if censor.whitelist {
+                exempt || listed
+            } else {
+                !exempt && listed
+            }
+        }),
+
+        // Attachments and phishing are handled separately in handle_censor
+        CensorType::Attachment | CensorType::Phishing => None,
+    };
+
+    if (censor.whitelist && found_filter.is_none()) || (!censor.whitelist && found_filter.is_some())
+    {
+        Some(found_filter)
+    } else {
+        None
+    }
+}
+
+impl EventHandler {
+    #[instrument(skip(self, ctx), fields(guild_id = %ctx.guild_id, user_id = %ctx.user.id, message_id = %ctx.message.id, channel_id = %ctx.channel_id))]
+    pub(crate) async fn handle_censor(
+        &self,
+        ctx: &Ctx<'_>,
+        typ: &CensorType,
+        censor: &Censor,
+        word_censor: Option<&Censor>,
+    ) -> DiscordResult<Option<AutomodResult>> {
+        let Some(found_filter) = self
+            .evaluate_censor(
+                ctx.guild_id,
+                &ctx.message.content,
+                &ctx.message.attachments,
+                typ,
+                censor,
+                word_censor,
+            )
+            .await
+        else {
+            return Ok(None);
         };
 
-        if (censor.whitelist && found_filter.is_none())
-            || (!censor.whitelist && found_filter.is_some())
-        {
+        if !censor.log_only {
             self.rest
                 .delete_message_and_forget(ctx.channel_id, &ctx.message.id)
                 .await;
+        }
+
+        // Log-only censors never count violations, so turning enforcement on
+        // later doesn't start members part way up the ladder
+        let window = escalation_window(typ, censor);
+        let violations = match window {
+            Some(window) => Some(
+                self.update_censor_violation_counter(ctx.guild_id, &ctx.user.id, typ, window)
+                    .await?,
+            ),
+            None => None,
+        };
+        let (action, duration) = censor_action(typ, censor, violations);
 
-            let expires_at =
-                chrono::Utc::now() + chrono::Duration::milliseconds(censor.action.duration);
+        let expires_at = chrono::Utc::now() + chrono::Duration::milliseconds(duration);
 
-            return Ok(Some(AutomodResult::new(Infraction::new_automod(
+        Ok(Some(AutomodResult::new(
+            Infraction::new_automod(
                 *ctx.guild_id,
                 ctx.user.id,
                 ctx.user.id,
-                censor.action.action.clone(),
+                action,
                 Some(expires_at.timestamp() as u64),
                 AutomodOffense {
                     typ: OffenseType::Censor(typ.clone()),
                     message: ctx.message.content.clone(),
-                    count: None,
-                    interval: None,
+                    count: violations,
+                    interval: window,
                     offending_filter: found_filter,
                 },
                 true,
-            ))));
+            ),
+            censor.log_only,
+        )))
+    }
+
+    /// Count a censor hit for a member within the escalation window and
+    /// return the number of hits still inside it.
+    #[instrument(skip(self))]
+    #[inline]
+    async fn update_censor_violation_counter(
+        &self,
+        guild_id: &Id,
+        user_id: &Id,
+        typ: &CensorType,
+        window_ms: u64,
+    ) -> DiscordResult<u64> {
+        self.update_redis_counter(&censor_violations_key(guild_id, user_id, typ), window_ms)
+            .await
+    }
+
+    /// Hits a member already has inside a censor's escalation window, without
+    /// counting a new one.
+    #[instrument(skip(self))]
+    #[inline]
+    pub(super) async fn peek_censor_violation_counter(
+        &self,
+        guild_id: &Id,
+        user_id: &Id,
+        typ: &CensorType,
+        window_ms: u64,
+    ) -> DiscordResult<u64> {
+        self.peek_redis_counter(&censor_violations_key(guild_id, user_id, typ), window_ms)
+            .await
+    }
+
+    /// Check content and attachments against a censor without acting on it.
+    ///
+    /// Returns `Some(found_filter)` when the censor is violated, see
+    /// [`find_censored_content`].
+    pub(super) async fn evaluate_censor(
+        &self,
+        guild_id: &Id,
+        content: &str,
+        attachments: &[Attachment],
+        typ: &CensorType,
+        censor: &Censor,
+        word_censor: Option<&Censor>,
+    ) -> Option<Option<String>> {
+        if !censor.enabled {
+            return None;
+        }
+
+        match typ {
+            CensorType::Attachment => {
+                let word_patterns: Vec<Pattern> = match word_censor {
+                    Some(word) if censor.match_filenames => {
+                        word.filters.iter().map(|p| Pattern::from_str(p)).collect()
+                    }
+                    _ => Vec::new(),
+                };
+
+                attachment_violation(censor, attachments, &word_patterns).map(Some)
+            }
+            CensorType::Phishing => self
+                .phishing
+                .find_phishing(content, &censor.filters)
+                .map(Some),
+            _ => {
+                // Resolve invites from the same text the censor matches on
+                let exempt_invites = match typ {
+                    CensorType::Invite if censor.allow_own_guild_invites => {
+                        self.own_guild_invites(guild_id, &censor_content(content, censor))
+                            .await
+                    }
+                    _ => Vec::new(),
+                };
+
+                find_censored_content(content, typ, censor, &exempt_invites)
+            }
+        }
+    }
+
+    /// Invite codes in the content that point to the given guild.
+    ///
+    /// Codes that cannot be resolved are treated as foreign so a failed
+    /// lookup never lets an invite through.
+    pub(super) async fn own_guild_invites(&self, guild_id: &Id, content: &str) -> Vec<String> {
+        let mut own = Vec::new();
+
+        for code in extract_invite_codes(content) {
+            match self.get_invite_guild(&code).await {
+                Ok(Some(invite_guild)) if invite_guild == *guild_id => own.push(code),
+                Ok(_) => {}
+                Err(e) => {
+                    tracing::debug!(code = %code, error = ?e, "failed to resolve invite");
+                }
+            }
         }
 
-        Ok(None)
+        own
     }
 }
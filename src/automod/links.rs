@@ -0,0 +1,132 @@
+use url::Url;
+
+/// Hosts that serve invites directly from the first path segment.
+const INVITE_SHORT_HOSTS: &[&str] = &["discord.gg", "dsc.gg"];
+
+/// Hosts that serve invites under `/invite/<code>`.
+const INVITE_PATH_HOSTS: &[&str] = &[
+    "discord.com",
+    "discordapp.com",
+    "ptb.discord.com",
+    "canary.discord.com",
+];
+
+/// Characters that wrap or separate links in markdown and should never be
+/// part of a link themselves: `[text](url)`, `<url>`, `||spoiler||`.
+const LINK_DELIMITERS: &[char] = &['<', '>', '(', ')', '[', ']', '|', '`', '"', '\''];
+
+/// Trailing punctuation that ends a sentence rather than a URL.
+const TRAILING_PUNCTUATION: &[char] = &['.', ',', '!', '?', ';', ':', '*', '~'];
+
+/// Split content into candidate link tokens.
+///
+/// Markdown link syntax is broken apart so both the visible text and the
+/// target of `[text](url)` are checked, and `<url>` embeds are unwrapped.
+fn tokens(content: &str) -> impl Iterator<Item = &str> {
+    content
+        .split(|c: char| c.is_whitespace() || LINK_DELIMITERS.contains(&c))
+        .map(|token| token.trim_end_matches(TRAILING_PUNCTUATION))
+        .filter(|token| !token.is_empty())
+}
+
+/// Parse a token as a URL, accepting bare domains (`example.com/path`).
+///
+/// Bare tokens are only accepted when they look like a domain, so ordinary
+/// words never parse as links.
+fn parse_link(token: &str) -> Option<Url> {
+    if token.contains("://") {
+        return Url::parse(token)
+            .ok()
+            .filter(|url| url.host_str().is_some());
+    }
+
+    let url = Url::parse(&format!("https://{token}")).ok()?;
+    let host = url.host_str()?;
+    let tld = host.rsplit('.').next()?;
+
+    if host.contains('.') && tld.len() >= 2 && tld.chars().all(|c| c.is_ascii_alphabetic()) {
+        Some(url)
+    } else {
+        None
+    }
+}
+
+/// Extract every link in the content as a parsed URL.
+pub(super) fn extract_links(content: &str) -> Vec<Url> {
+    tokens(content).filter_map(parse_link).collect()
+}
+
+/// Lowercased host of a URL with any leading `www.` removed.
+pub(super) fn normalized_host(url: &Url) -> Option<String> {
+    url.host_str().map(|host| {
+        let host = host.trim_end_matches('.').to_lowercase();
+        host.strip_prefix("www.")
+            .map(str::to_string)
+            .unwrap_or(host)
+    })
+}
+
+/// Whether `host` is covered by a domain filter.
+///
+/// A filter matches its own domain and every subdomain of it, so
+/// `example.com` covers `cdn.example.com`. A leading `*.` limits the filter
+/// to subdomains only. Subdomain matches never cross the host's registrable
+/// domain (eTLD+1), so `github.io` or `co.uk` don't cover every site hosted
+/// under them.
+pub(super) fn domain_matches(filter: &str, host: &str) -> bool {
+    let filter = filter.trim().trim_end_matches('.').to_lowercase();
+
+    let (parent, subdomains_only) = match filter.strip_prefix("*.") {
+        Some(parent) => (parent, true),
+        None => (filter.strip_prefix("www.").unwrap_or(&filter), false),
+    };
+
+    if host == parent {
+        return !subdomains_only;
+    }
+
+    let is_subdomain = host
+        .strip_suffix(parent)
+        .is_some_and(|rest| rest.ends_with('.'));
+
+    is_subdomain && within_registrable_domain(parent, host)
+}
+
+/// Whether `domain` is the host's registrable domain or a subdomain of it.
+fn within_registrable_domain(domain: &str, host: &str) -> bool {
+    let Some(registrable) = psl::domain_str(host) else {
+        return false;
+    };
+
+    domain == registrable
+        || domain
+            .strip_suffix(registrable)
+            .is_some_and(|rest| rest.ends_with('.'))
+}
+
+/// Extract the invite code from a Discord invite URL, preserving its case.
+pub(super) fn invite_code(url: &Url) -> Option<String> {
+    let host = normalized_host(url)?;
+    let mut segments = url.path_segments()?.filter(|s| !s.is_empty());
+
+    let code = if INVITE_SHORT_HOSTS.contains(&host.as_str()) {
+        segments.next()?
+    } else if INVITE_PATH_HOSTS.contains(&host.as_str()) {
+        if segments.next()? != "invite" {
+            return None;
+        }
+        segments.next()?
+    } else {
+        return None;
+    };
+
+    Some(code.to_string())
+}
+
+/// Extract every Discord invite code in the content.
+pub(super) fn extract_invite_codes(content: &str) -> Vec<String> {
+    extract_links(content)
+        .iter()
+        .filter_map(invite_code)
+        .collect()
+}
@@ -1,15 +1,26 @@
+mod account_age;
+mod attachment;
 mod censor;
+mod links;
 mod macros;
+mod names;
+mod phishing;
+mod policy;
+mod raid;
+mod simulate;
 mod spam;
 
-use std::collections::HashMap;
+pub use phishing::PhishingDatabase;
+pub use simulate::AutomodSimulation;
+
+use std::{borrow::Cow, collections::HashMap, fmt};
 
 use crate::{check_bypass, handler::EventHandler};
 
 use bm_lib::{
-    discord::{commands::Ctx, DiscordResult, EmbedBuilder},
+    discord::{commands::Ctx, DiscordResult, EmbedBuilder, Id},
     model::{
-        automod::{AutomodSettings, OffenseType},
+        automod::{AutomodSettings, Bypass, CensorType, OffenseType},
         logging::LogEvent,
         Config, Infraction,
     },
@@ -19,14 +30,108 @@ use tracing::instrument;
 #[derive(Debug)]
 pub struct AutomodResult {
     pub infraction: Infraction,
+    /// The filter is in log-only mode: the match is logged but the message is
+    /// kept and no infraction is recorded.
+    pub simulated: bool,
 }
 
 impl AutomodResult {
-    pub fn new(infraction: Infraction) -> Self {
-        Self { infraction }
+    pub fn new(infraction: Infraction, simulated: bool) -> Self {
+        Self {
+            infraction,
+            simulated,
+        }
+    }
+}
+
+/// Where the automod settings applied to a message came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingsSource {
+    Global,
+    /// Override configured on the channel itself. `merged` is set when it is
+    /// layered on top of the global settings.
+    Channel {
+        id: Id,
+        merged: bool,
+    },
+    /// Override inherited from the category, or for threads from the parent
+    /// channel or its category.
+    Parent {
+        id: Id,
+        merged: bool,
+    },
+}
+
+impl fmt::Display for SettingsSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (scope, id, merged) = match self {
+            SettingsSource::Global => return write!(f, "global"),
+            SettingsSource::Channel { id, merged } => ("channel", id, merged),
+            SettingsSource::Parent { id, merged } => ("inherited from", id, merged),
+        };
+
+        if *merged {
+            write!(f, "global + {scope} <#{id}>")
+        } else {
+            write!(f, "{scope} <#{id}>")
+        }
     }
 }
 
+/// Mask all but the first and last character of a word.
+pub(crate) fn mask_word(word: &str) -> String {
+    let count = word.chars().count();
+    if count <= 2 {
+        return "*".repeat(count);
+    }
+
+    let mut chars = word.chars();
+    let first = chars.next().unwrap_or_default();
+    let last = chars.next_back().unwrap_or_default();
+    format!("{first}{}{last}", "*".repeat(count - 2))
+}
+
+/// Up to 15 bytes either side of the first match of `word`, with the match
+/// itself masked. The match is found case-insensitively and masked by
+/// position so the original casing never leaks.
+fn censored_context(content: &str, word: &str) -> Option<String> {
+    let needle = word.to_lowercase();
+    if needle.is_empty() {
+        return None;
+    }
+
+    // Positions come from the lowercased text, whose byte offsets can
+    // differ, so snap them to char boundaries
+    let pos = content.to_lowercase().find(&needle)?;
+    let word_start = floor_char_boundary(content, pos);
+    let word_end = ceil_char_boundary(content, pos + needle.len());
+    let start = floor_char_boundary(content, word_start.saturating_sub(15));
+    let end = ceil_char_boundary(content, word_end + 15);
+
+    Some(format!(
+        "{}{}{}",
+        content.get(start..word_start)?,
+        mask_word(content.get(word_start..word_end)?),
+        content.get(word_end..end)?
+    ))
+}
+
+fn floor_char_boundary(s: &str, index: usize) -> usize {
+    let mut index = index.min(s.len());
+    while !s.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+fn ceil_char_boundary(s: &str, index: usize) -> usize {
+    let mut index = index.min(s.len());
+    while !s.is_char_boundary(index) {
+        index += 1;
+    }
+    index
+}
+
 /// Merge global and channel settings when inherit_global is true.
 /// Channel settings take precedence over global settings.
 fn merge_settings(global: &AutomodSettings, channel: &AutomodSettings) -> AutomodSettings {
@@ -62,44 +167,195 @@ fn merge_settings(global: &AutomodSettings, channel: &AutomodSettings) -> Automo
     merged
 }
 
+/// Resolve the automod settings that apply to a channel.
+///
+/// `lineage` lists the channel followed by its ancestors, most specific
+/// first: the channel, then its category, or for threads the thread, its
+/// parent channel and that channel's category. The first enabled override
+/// found wins, disabled overrides are skipped. When no override applies the
+/// global settings are used if enabled.
+fn resolve_settings(
+    global: Option<&AutomodSettings>,
+    channels: &HashMap<Id, AutomodSettings>,
+    lineage: &[Id],
+) -> Option<(AutomodSettings, SettingsSource)> {
+    let found = lineage.iter().enumerate().find_map(|(depth, id)| {
+        channels
+            .get(id)
+            .filter(|settings| settings.enabled)
+            .map(|settings| (depth, id, settings))
+    });
+
+    match (global, found) {
+        (global, Some((depth, id, c))) => {
+            let merged = c.inherit_global && global.is_some();
+            let source = if depth == 0 {
+                SettingsSource::Channel { id: *id, merged }
+            } else {
+                SettingsSource::Parent { id: *id, merged }
+            };
+
+            match global {
+                // Merge global and channel settings
+                Some(g) if c.inherit_global => Some((merge_settings(g, c), source)),
+                // Use only channel settings
+                _ => Some((c.clone(), source)),
+            }
+        }
+        // No override applies, use global if enabled
+        (Some(g), None) if g.enabled => Some((g.clone(), SettingsSource::Global)),
+        // No valid settings
+        _ => None,
+    }
+}
+
 impl EventHandler {
-    #[instrument(skip(self, ctx), fields(guild_id = %ctx.guild_id, user_id = %ctx.user.id, message_id = %ctx.message.id, channel_id = %ctx.channel_id))]
+    #[instrument(skip(self, ctx), fields(guild_id = %ctx.guild_id, user_id = %ctx.user.id, message_id = %ctx.message.id, channel_id = %ctx.channel_id, settings))]
     pub async fn handle_automod(&self, config: &Config, ctx: &Ctx<'_>) -> DiscordResult<()> {
-        let Some(automod) = &config.automod else {
-            return Ok(());
+        self.run_automod(config, ctx, false).await
+    }
+
+    /// Run automod against an edited message. Only censors are checked; edits
+    /// never count towards spam windows.
+    #[instrument(skip(self, ctx), fields(guild_id = %ctx.guild_id, user_id = %ctx.user.id, message_id = %ctx.message.id, channel_id = %ctx.channel_id, settings))]
+    pub async fn handle_automod_edit(&self, config: &Config, ctx: &Ctx<'_>) -> DiscordResult<()> {
+        self.run_automod(config, ctx, true).await
+    }
+
+    async fn run_automod(&self, config: &Config, ctx: &Ctx<'_>, edited: bool) -> DiscordResult<()> {
+        let settings = self
+            .resolve_member_settings(
+                config,
+                ctx.guild_id,
+                ctx.channel_id,
+                &ctx.user.id,
+                &ctx.roles,
+            )
+            .await;
+
+        if let Some((settings, source, _)) = settings {
+            tracing::Span::current().record("settings", tracing::field::display(&source));
+
+            if let Some(result) = self.process_automod(config, ctx, &settings, edited).await? {
+                self.process_automod_infraction(config, ctx, &result, source, edited)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Settings that apply to a member in a channel: the channel's resolved
+    /// settings with the member's role policies applied on top. Also returns
+    /// the names of the policies that were applied.
+    pub(super) async fn resolve_member_settings(
+        &self,
+        config: &Config,
+        guild_id: &Id,
+        channel_id: &Id,
+        user_id: &Id,
+        roles: &[Id],
+    ) -> Option<(AutomodSettings, SettingsSource, Vec<String>)> {
+        let automod = config.automod.as_ref()?;
+
+        let lineage = self
+            .settings_lineage(guild_id, channel_id, &automod.channels)
+            .await;
+
+        let (mut settings, source) =
+            resolve_settings(automod.global.as_ref(), &automod.channels, &lineage)?;
+
+        let mut applied = Vec::new();
+        if !automod.policies.is_empty() {
+            let joined_at = if policy::needs_join_time(&automod.policies) {
+                self.member_joined_at(guild_id, user_id).await
+            } else {
+                None
+            };
+
+            applied = policy::apply_policies(&mut settings, &automod.policies, roles, joined_at);
+            if !applied.is_empty() {
+                tracing::debug!(policies = ?applied, "applied role policies");
+            }
+        }
+
+        Some((settings, source, applied))
+    }
+
+    /// Whether a member is exempt under `bypass`, directly, through one of
+    /// their roles or through a permission group.
+    pub(crate) async fn is_automod_bypassed(
+        &self,
+        config: &Config,
+        user_id: &Id,
+        roles: &[Id],
+        bypass: Option<&Bypass>,
+    ) -> bool {
+        let Some(bypass) = bypass else {
+            return false;
         };
 
-        let global = automod.global.as_ref();
-        let channel = automod.channels.get(&ctx.channel_id);
-
-        // Determine which settings to use
-        let settings = match (global, channel) {
-            // Channel override exists
-            (Some(g), Some(c)) if c.enabled => {
-                if c.inherit_global {
-                    // Merge global and channel settings
-                    Some(merge_settings(g, c))
-                } else {
-                    // Use only channel settings
-                    Some(c.clone())
-                }
+        if bypass.users.contains(user_id) {
+            return true;
+        }
+
+        if bypass.roles.iter().any(|role| roles.contains(role)) {
+            return true;
+        }
+
+        // Same membership rules as permission checks, so members in a group
+        // through one of their roles are bypassed too
+        self.get_user_groups(config, user_id, roles)
+            .await
+            .iter()
+            .any(|group| bypass.groups.contains(&group.name))
+    }
+
+    /// When the member joined the guild, in ms since the epoch.
+    async fn member_joined_at(&self, guild_id: &Id, user_id: &Id) -> Option<i64> {
+        let member = match self.get_member(guild_id, user_id).await {
+            Ok(member) => member,
+            Err(e) => {
+                tracing::debug!(error = ?e, "failed to fetch member for role policies");
+                return None;
             }
-            // No channel override, use global if enabled
-            (Some(g), None) if g.enabled => Some(g.clone()),
-            // Channel exists but not enabled, check global
-            (Some(g), Some(c)) if !c.enabled && g.enabled => Some(g.clone()),
-            // No valid settings
-            _ => None,
         };
 
-        if let Some(settings) = settings {
-            if let Some(result) = self.process_automod(config, ctx, &settings).await? {
-                self.process_automod_infraction(config, ctx, &result.infraction)
-                    .await?;
+        chrono::DateTime::parse_from_rfc3339(&member.joined_at)
+            .ok()
+            .map(|joined_at| joined_at.timestamp_millis())
+    }
+
+    /// The channel followed by the ancestors whose overrides it inherits, see
+    /// [`resolve_settings`]. Parents are only looked up when the channel has
+    /// no enabled override of its own.
+    pub(super) async fn settings_lineage(
+        &self,
+        guild_id: &Id,
+        channel_id: &Id,
+        channels: &HashMap<Id, AutomodSettings>,
+    ) -> Vec<Id> {
+        let mut lineage = vec![*channel_id];
+
+        let has_own = channels.get(channel_id).is_some_and(|c| c.enabled);
+        if has_own || channels.is_empty() {
+            return lineage;
+        }
+
+        // At most thread -> parent channel -> category
+        while lineage.len() < 3 {
+            let current = lineage[lineage.len() - 1];
+            match self.get_channel_parent(guild_id, &current).await {
+                Ok(Some(parent_id)) => lineage.push(parent_id),
+                Ok(None) => break,
+                Err(e) => {
+                    tracing::debug!(channel_id = %current, error = ?e, "failed to resolve channel parent");
+                    break;
+                }
             }
         }
 
-        Ok(())
+        lineage
     }
 
     #[instrument(skip(self, ctx), fields(guild_id = %ctx.guild_id, user_id = %ctx.user.id, message_id = %ctx.message.id, channel_id = %ctx.channel_id))]
@@ -108,17 +364,23 @@ impl EventHandler {
         config: &Config,
         ctx: &Ctx<'_>,
         automod: &AutomodSettings,
+        edited: bool,
     ) -> DiscordResult<Option<AutomodResult>> {
         check_bypass!(self, config, ctx, &automod.bypass);
 
         if let Some(censors) = &automod.censors {
+            let word_censor = censors.get(&CensorType::Word);
             for (typ, censor) in censors {
-                if let Some(result) = self.handle_censor(ctx, typ, censor).await? {
+                if let Some(result) = self.handle_censor(ctx, typ, censor, word_censor).await? {
                     return Ok(Some(result));
                 }
             }
         }
 
+        if edited {
+            return Ok(None);
+        }
+
         if let Some(spam) = &automod.spam {
             check_bypass!(self, config, ctx, &spam.bypass);
             if let Some(result) = self.handle_spam(ctx, spam).await? {
@@ -134,13 +396,36 @@ impl EventHandler {
         &self,
         config: &Config,
         ctx: &Ctx<'_>,
-        infraction: &Infraction,
+        result: &AutomodResult,
+        source: SettingsSource,
+        edited: bool,
     ) -> DiscordResult<()> {
+        let infraction = &result.infraction;
+
         // For None infraction type, skip creating the infraction record
-        // (message deletion and log event still happen below)
+        // (message deletion and log event still happen below).
+        // Log-only filters never record an infraction.
         use bm_lib::model::InfractionType;
-        if infraction.infraction_type != InfractionType::None {
-            self.db.create_infraction(infraction).await?;
+        if infraction.infraction_type != InfractionType::None && !result.simulated {
+            match (&infraction.infraction_type, &config.quarantine_role) {
+                (InfractionType::Quarantine, Some(quarantine_role)) => {
+                    // A member who is already quarantined, or being quarantined
+                    // for another message, keeps that infraction
+                    self.record_quarantine(
+                        ctx.guild_id,
+                        &ctx.user.id,
+                        quarantine_role,
+                        infraction.clone(),
+                        Some(Cow::Borrowed("Automod quarantine")),
+                    )
+                    .await?;
+                }
+                (InfractionType::Quarantine, None) => {
+                    tracing::warn!("Automod quarantine triggered without a quarantine role");
+                    self.db.create_infraction(infraction).await?;
+                }
+                _ => self.db.create_infraction(infraction).await?,
+            }
         }
 
         let reason = if let Some(offense) = infraction.automod_offense.as_ref() {
@@ -154,33 +439,21 @@ impl EventHandler {
                     )
                 }
                 OffenseType::Censor(typ) => {
-                    let typ_str = typ.to_pretty_string();
+                    let typ_str = match offense.count {
+                        Some(count) => format!("{} (violation #{count})", typ.to_pretty_string()),
+                        None => typ.to_pretty_string(),
+                    };
 
                     let offending_word = offense.offending_filter.clone().unwrap_or_default();
                     let message_content = ctx.message.content.as_str();
 
-                    let censored = if offending_word.len() > 2 {
-                        format!(
-                            "{}{}{}",
-                            &offending_word[..1],
-                            "*".repeat(offending_word.len() - 2),
-                            &offending_word[offending_word.len() - 1..]
-                        )
-                    } else {
-                        "*".repeat(offending_word.len())
-                    };
-
-                    if let Some(pos) = message_content
-                        .to_lowercase()
-                        .find(&offending_word.to_lowercase())
-                    {
-                        let start = pos.saturating_sub(15);
-                        let end = (pos + offending_word.len() + 15).min(message_content.len());
-                        let context =
-                            message_content[start..end].replace(&offending_word, &censored);
-                        format!("{typ_str} censor triggered: \"...{context}...\"")
-                    } else {
-                        format!("{typ_str} censor triggered: {censored}")
+                    match censored_context(message_content, &offending_word) {
+                        Some(context) => {
+                            format!("{typ_str} censor triggered: \"...{context}...\"")
+                        }
+                        None => {
+                            format!("{typ_str} censor triggered: {}", mask_word(&offending_word))
+                        }
                     }
                 }
             }
@@ -191,6 +464,18 @@ impl EventHandler {
                 .unwrap_or_else(|| "Automod action triggered".to_string())
         };
 
+        let reason = if edited {
+            format!("{reason} (edited message)")
+        } else {
+            reason
+        };
+
+        let reason = if result.simulated {
+            format!("[Simulated] {reason}")
+        } else {
+            reason
+        };
+
         // Build typed log event for the logging system
         let log_event = if let Some(offense) = infraction.automod_offense.as_ref() {
             match &offense.typ {
@@ -203,6 +488,8 @@ impl EventHandler {
                     spam_type: typ.to_pretty_string(),
                     count: offense.count.unwrap_or(0),
                     interval: offense.interval.unwrap_or(0),
+                    simulated: result.simulated,
+                    settings: source.to_string(),
                 },
                 OffenseType::Censor(typ) => LogEvent::AutomodCensor {
                     guild_id: *ctx.guild_id,
@@ -212,6 +499,8 @@ impl EventHandler {
                     reason: reason.clone(),
                     filter_type: typ.to_pretty_string(),
                     offending_content: offense.offending_filter.clone().unwrap_or_default(),
+                    simulated: result.simulated,
+                    settings: source.to_string(),
                 },
             }
         } else {
@@ -223,6 +512,8 @@ impl EventHandler {
                 reason: reason.clone(),
                 filter_type: String::new(),
                 offending_content: String::new(),
+                simulated: result.simulated,
+                settings: source.to_string(),
             }
         };
 
@@ -247,3 +538,38 @@ impl EventHandler {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn censored_context_masks_any_casing() {
+        assert_eq!(
+            censored_context("this is a BadWord here", "badword").as_deref(),
+            Some("this is a B*****d here")
+        );
+    }
+
+    #[test]
+    fn censored_context_keeps_surrounding_text() {
+        let content = "a".repeat(20) + "slur" + &"b".repeat(20);
+        assert_eq!(
+            censored_context(&content, "slur"),
+            Some(format!("{}s**r{}", "a".repeat(15), "b".repeat(15)))
+        );
+    }
+
+    #[test]
+    fn censored_context_handles_multibyte_text() {
+        let context = censored_context("ÀÀÀÀÀÀÀÀÀÀ Slur ÉÉÉÉÉÉÉÉÉÉ", "slur").unwrap();
+        assert!(context.contains("S**r"));
+        assert!(!context.to_lowercase().contains("slur"));
+    }
+
+    #[test]
+    fn censored_context_without_match() {
+        assert_eq!(censored_context("clean message", "slur"), None);
+        assert_eq!(censored_context("clean message", ""), None);
+    }
+}
@@ -0,0 +1,330 @@
+use std::{borrow::Cow, future::Future, time::Duration};
+
+use bm_lib::{
+    discord::{DiscordError, DiscordResult, Id, User},
+    emojis::Emoji,
+    model::{
+        automod::{CensorType, NameFilter},
+        logging::LogEvent,
+        Config,
+    },
+};
+use tracing::instrument;
+
+use crate::handler::{groups::member_groups, EventHandler};
+
+use super::censor::find_censored_content;
+
+/// Members fetched per page during a dehoist sweep.
+const SWEEP_PAGE_SIZE: u64 = 1000;
+
+/// Members checked between progress messages during a dehoist sweep.
+const SWEEP_PROGRESS_EVERY: usize = 5000;
+
+/// First wait after a rate limit when Discord asks for less, doubled on
+/// every retry.
+const SWEEP_BACKOFF_BASE: Duration = Duration::from_secs(1);
+const SWEEP_BACKOFF_MAX: Duration = Duration::from_secs(60);
+const SWEEP_MAX_RETRIES: u32 = 5;
+
+/// Longest a sweep holds the guild's claim, in case its shard dies mid-sweep.
+const SWEEP_CLAIM_TTL: Duration = Duration::from_secs(60 * 60);
+
+#[inline]
+fn name_sweep_cache_key(guild_id: &Id) -> String {
+    format!("name_sweep:{}", guild_id)
+}
+
+/// Members checked and nicknames changed by a dehoist sweep.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SweepProgress {
+    pub checked: usize,
+    pub changed: usize,
+}
+
+/// Run a request, waiting and retrying while Discord rate limits it.
+async fn with_backoff<T, F, Fut>(mut request: F) -> DiscordResult<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = DiscordResult<T>>,
+{
+    let mut attempt = 0;
+
+    loop {
+        match request().await {
+            Err(DiscordError::RateLimited(retry_after)) if attempt < SWEEP_MAX_RETRIES => {
+                let wait = retry_after
+                    .max(SWEEP_BACKOFF_BASE * 2_u32.pow(attempt))
+                    .min(SWEEP_BACKOFF_MAX);
+                tracing::debug!(wait = ?wait, attempt, "rate limited during name sweep");
+
+                tokio::time::sleep(wait).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// What to do with a member's nickname.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum NameChange {
+    /// Clear the nickname so the account name shows again.
+    Reset,
+    /// Set the nickname to the given value.
+    Set(String),
+}
+
+fn is_hoisted(filter: &NameFilter, name: &str) -> bool {
+    name.chars()
+        .next()
+        .is_some_and(|c| filter.hoist_characters.contains(c))
+}
+
+/// Decide how a member's name should change, if at all.
+///
+/// The name checked is what other members see: nickname, then global name,
+/// then username. Offensive names are reset when the name underneath is
+/// clean and replaced otherwise; hoisted names have the leading hoist
+/// characters stripped.
+fn moderate_name(
+    filter: &NameFilter,
+    config: &Config,
+    user: &User,
+    nick: Option<&str>,
+) -> Option<(NameChange, &'static str)> {
+    let account_name = user.global_name.as_deref().unwrap_or(&user.username);
+    let display_name = nick.unwrap_or(account_name);
+
+    let word_censor = config
+        .automod
+        .as_ref()
+        .and_then(|automod| automod.global.as_ref())
+        .and_then(|global| global.censors.as_ref())
+        .and_then(|censors| censors.get(&CensorType::Word))
+        // A whitelist would flag nearly every name
+        .filter(|censor| filter.check_words && censor.enabled && !censor.whitelist);
+
+    let offensive = |name: &str| {
+        word_censor.is_some_and(|censor| {
+            find_censored_content(name, &CensorType::Word, censor, &[]).is_some()
+        })
+    };
+
+    if offensive(display_name) {
+        let change =
+            if nick.is_some() && !offensive(account_name) && !is_hoisted(filter, account_name) {
+                NameChange::Reset
+            } else {
+                NameChange::Set(filter.replacement.clone())
+            };
+        return Some((change, "Name matched word filter"));
+    }
+
+    if filter.dehoist && is_hoisted(filter, display_name) {
+        let stripped = display_name
+            .trim_start_matches(|c: char| filter.hoist_characters.contains(c) || c.is_whitespace());
+
+        let change = if stripped.is_empty() {
+            NameChange::Set(filter.replacement.clone())
+        } else {
+            NameChange::Set(stripped.to_string())
+        };
+        return Some((change, "Hoisted name"));
+    }
+
+    None
+}
+
+impl EventHandler {
+    /// Run the name filter against a member and fix their nickname if needed.
+    ///
+    /// Returns whether the nickname was changed.
+    #[instrument(skip(self, config, user, roles), fields(user_id = %user.id))]
+    pub async fn handle_member_name(
+        &self,
+        config: &Config,
+        guild_id: &Id,
+        user: &User,
+        nick: Option<&str>,
+        roles: &[Id],
+    ) -> DiscordResult<bool> {
+        if !config.automod_enabled || user.bot {
+            return Ok(false);
+        }
+
+        let Some(filter) = config
+            .automod
+            .as_ref()
+            .and_then(|automod| automod.names.as_ref())
+            .filter(|filter| filter.enabled)
+        else {
+            return Ok(false);
+        };
+
+        if let Some(bypass) = &filter.bypass {
+            if bypass.users.contains(&user.id)
+                || bypass.roles.iter().any(|role| roles.contains(role))
+                || member_groups(config, &user.id, roles)
+                    .iter()
+                    .any(|group| bypass.groups.contains(&group.name))
+            {
+                return Ok(false);
+            }
+        }
+
+        let Some((change, reason)) = moderate_name(filter, config, user, nick) else {
+            return Ok(false);
+        };
+
+        let new_nick = match &change {
+            NameChange::Reset => None,
+            NameChange::Set(name) => Some(name.as_str()),
+        };
+
+        // Already applied, e.g. the update event caused by our own change
+        if new_nick == nick {
+            return Ok(false);
+        }
+
+        self.rest
+            .modify_member_nickname(guild_id, &user.id, new_nick, Some(Cow::Borrowed(reason)))
+            .await?;
+
+        let _ = self
+            .log_event(LogEvent::NicknameModerated {
+                guild_id: *guild_id,
+                user_id: user.id,
+                username: user.username.to_string(),
+                // The name that was checked, see `moderate_name`
+                old_name: nick
+                    .or(user.global_name.as_deref())
+                    .unwrap_or(&user.username)
+                    .to_string(),
+                new_name: new_nick.map(str::to_string),
+                reason: reason.to_string(),
+            })
+            .await;
+
+        Ok(true)
+    }
+
+    /// Start a dehoist sweep in the background, reporting progress and the
+    /// result to `channel_id`. Returns `false` when a sweep is already running
+    /// for the guild.
+    pub async fn spawn_member_name_sweep(
+        &self,
+        config: Config,
+        guild_id: Id,
+        channel_id: Id,
+    ) -> DiscordResult<bool> {
+        // Only one sweep per guild runs at a time across shards
+        let Some(claim) = self
+            .claim_guarded(name_sweep_cache_key(&guild_id), SWEEP_CLAIM_TTL)
+            .await?
+        else {
+            return Ok(false);
+        };
+
+        let handler = self.clone();
+        tokio::spawn(async move {
+            let message = match handler
+                .sweep_member_names(&config, &guild_id, &channel_id)
+                .await
+            {
+                Ok(progress) => format!(
+                    "{} Checked {} members, updated {} name{}",
+                    Emoji::Check,
+                    progress.checked,
+                    progress.changed,
+                    if progress.changed == 1 { "" } else { "s" }
+                ),
+                Err(e) => {
+                    tracing::error!(guild_id = %guild_id, error = ?e, "Failed to sweep member names");
+                    format!("{} Name sweep failed, try again later", Emoji::Cross)
+                }
+            };
+
+            handler
+                .rest
+                .create_message_and_forget(&channel_id, &message)
+                .await;
+
+            claim.release().await;
+        });
+
+        Ok(true)
+    }
+
+    /// Run the name filter against every member of the guild, posting
+    /// progress to `channel_id` as it goes.
+    ///
+    /// Members that fail to update, usually because they outrank the bot, are
+    /// skipped. Rate limited requests are retried with backoff.
+    #[instrument(skip(self, config))]
+    async fn sweep_member_names(
+        &self,
+        config: &Config,
+        guild_id: &Id,
+        channel_id: &Id,
+    ) -> DiscordResult<SweepProgress> {
+        let mut progress = SweepProgress::default();
+        let mut after: Option<Id> = None;
+
+        loop {
+            let members = with_backoff(|| {
+                self.rest
+                    .list_guild_members(guild_id, after, SWEEP_PAGE_SIZE)
+            })
+            .await?;
+
+            for member in &members {
+                let Some(user) = member.user.as_ref() else {
+                    continue;
+                };
+
+                match with_backoff(|| {
+                    self.handle_member_name(
+                        config,
+                        guild_id,
+                        user,
+                        member.nick.as_deref(),
+                        &member.roles,
+                    )
+                })
+                .await
+                {
+                    Ok(true) => progress.changed += 1,
+                    Ok(false) => {}
+                    Err(e) => {
+                        tracing::debug!(user_id = %user.id, error = ?e, "failed to moderate name");
+                    }
+                }
+
+                progress.checked += 1;
+                if progress.checked % SWEEP_PROGRESS_EVERY == 0 {
+                    self.rest
+                        .create_message_and_forget(
+                            channel_id,
+                            &format!(
+                                "Checked {} members so far, updated {}",
+                                progress.checked, progress.changed
+                            ),
+                        )
+                        .await;
+                }
+            }
+
+            if (members.len() as u64) < SWEEP_PAGE_SIZE {
+                break;
+            }
+
+            after = members.last().and_then(|m| m.user.as_ref()).map(|u| u.id);
+            if after.is_none() {
+                break;
+            }
+        }
+
+        Ok(progress)
+    }
+}
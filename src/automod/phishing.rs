@@ -0,0 +1,398 @@
+use std::{
+    collections::HashSet,
+    path::PathBuf,
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use super::links::{extract_links, normalized_host};
+
+/// Domains that belong to the brands we protect. Links to these (or their
+/// subdomains) are always legitimate and are never flagged as look-alikes.
+const PROTECTED_DOMAINS: &[&str] = &[
+    "discord.com",
+    "discord.gg",
+    "discord.gift",
+    "discord.gifts",
+    "discord.media",
+    "discord.new",
+    "discord.dev",
+    "discord.co",
+    "discordapp.com",
+    "discordapp.net",
+    "discordcdn.com",
+    "discordstatus.com",
+    "steamcommunity.com",
+    "steampowered.com",
+    "steamstatic.com",
+    "steamgames.com",
+    "steamchina.com",
+];
+
+/// Confusable characters mapped to the ASCII letter they imitate.
+const HOMOGLYPHS: &[(char, char)] = &[
+    ('0', 'o'),
+    ('1', 'i'),
+    ('l', 'i'),
+    ('3', 'e'),
+    ('4', 'a'),
+    ('5', 's'),
+    ('7', 't'),
+    ('8', 'b'),
+    ('і', 'i'),
+    ('ı', 'i'),
+    ('í', 'i'),
+    ('ì', 'i'),
+    ('а', 'a'),
+    ('á', 'a'),
+    ('à', 'a'),
+    ('å', 'a'),
+    ('с', 'c'),
+    ('ç', 'c'),
+    ('ԁ', 'd'),
+    ('ɗ', 'd'),
+    ('е', 'e'),
+    ('é', 'e'),
+    ('è', 'e'),
+    ('ë', 'e'),
+    ('ɡ', 'g'),
+    ('һ', 'h'),
+    ('ј', 'j'),
+    ('к', 'k'),
+    ('ӏ', 'l'),
+    ('м', 'm'),
+    ('п', 'n'),
+    ('ո', 'n'),
+    ('о', 'o'),
+    ('ο', 'o'),
+    ('ó', 'o'),
+    ('ö', 'o'),
+    ('р', 'p'),
+    ('ρ', 'p'),
+    ('ԛ', 'q'),
+    ('г', 'r'),
+    ('ѕ', 's'),
+    ('т', 't'),
+    ('υ', 'u'),
+    ('ü', 'u'),
+    ('ú', 'u'),
+    ('ν', 'v'),
+    ('ѡ', 'w'),
+    ('х', 'x'),
+    ('у', 'y'),
+    ('ý', 'y'),
+];
+
+/// Multi-character sequences that render like a single letter.
+const DIGRAPHS: &[(&str, &str)] = &[("rn", "m"), ("vv", "w"), ("cl", "d")];
+
+/// Locally stored list of known phishing domains.
+///
+/// The list is read from a plain text file, one domain per line, with `#`
+/// starting a comment. Operators update it by replacing the file; [`watch`]
+/// picks up changes without a restart and no network access is needed.
+///
+/// [`watch`]: PhishingDatabase::watch
+pub struct PhishingDatabase {
+    path: Option<PathBuf>,
+    domains: RwLock<HashSet<String>>,
+    modified: Mutex<Option<SystemTime>>,
+}
+
+impl PhishingDatabase {
+    /// Create an empty database, load it with [`reload_if_changed`].
+    ///
+    /// [`reload_if_changed`]: PhishingDatabase::reload_if_changed
+    pub fn new(path: Option<PathBuf>) -> Self {
+        Self {
+            path,
+            domains: RwLock::new(HashSet::new()),
+            modified: Mutex::new(None),
+        }
+    }
+
+    /// Re-read the domain list if the file has been modified since the last load.
+    /// Returns `true` when the list was reloaded.
+    pub async fn reload_if_changed(&self) -> std::io::Result<bool> {
+        let Some(path) = &self.path else {
+            return Ok(false);
+        };
+
+        let modified = tokio::fs::metadata(path).await?.modified()?;
+        {
+            let last = self.modified.lock().unwrap_or_else(|e| e.into_inner());
+            if *last == Some(modified) {
+                return Ok(false);
+            }
+        }
+
+        let contents = tokio::fs::read_to_string(path).await?;
+        let domains: HashSet<String> = contents
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default().trim())
+            .filter(|line| !line.is_empty())
+            .map(|line| line.trim_end_matches('.').to_lowercase())
+            .collect();
+
+        tracing::info!(count = domains.len(), path = %path.display(), "loaded phishing domain list");
+
+        *self.domains.write().unwrap_or_else(|e| e.into_inner()) = domains;
+        *self.modified.lock().unwrap_or_else(|e| e.into_inner()) = Some(modified);
+
+        Ok(true)
+    }
+
+    /// Poll the domain list file for changes.
+    pub async fn watch(self: Arc<Self>, interval: Duration) {
+        if self.path.is_none() {
+            return;
+        }
+
+        loop {
+            tokio::time::sleep(interval).await;
+            if let Err(e) = self.reload_if_changed().await {
+                tracing::warn!(error = %e, "failed to reload phishing domain list");
+            }
+        }
+    }
+
+    /// Whether the host, or any domain it is a subdomain of, is listed.
+    fn is_listed(&self, host: &str) -> bool {
+        let domains = self.domains.read().unwrap_or_else(|e| e.into_inner());
+        if domains.is_empty() {
+            return false;
+        }
+
+        let mut candidate = host;
+        loop {
+            if domains.contains(candidate) {
+                return true;
+            }
+            match candidate.split_once('.') {
+                Some((_, parent)) if parent.contains('.') => candidate = parent,
+                _ => return false,
+            }
+        }
+    }
+
+    /// Find the first link in the content that is a known phishing domain or
+    /// imitates a protected domain. `extra_protected` adds guild-specific
+    /// domains to the built-in protected list.
+    pub fn find_phishing(&self, content: &str, extra_protected: &[String]) -> Option<String> {
+        let protected: Vec<String> = PROTECTED_DOMAINS
+            .iter()
+            .map(|d| d.to_string())
+            .chain(extra_protected.iter().map(|d| d.trim().to_lowercase()))
+            .collect();
+
+        extract_links(content)
+            .iter()
+            .filter_map(normalized_host)
+            .find(|host| self.is_listed(host) || is_lookalike(host, &protected))
+    }
+}
+
+/// Whether `host` is, or is a subdomain of, `domain`.
+#[inline]
+fn is_same_or_subdomain(host: &str, domain: &str) -> bool {
+    host == domain
+        || host
+            .strip_suffix(domain)
+            .is_some_and(|rest| rest.ends_with('.'))
+}
+
+/// The label a domain is recognised by, the first label of its registrable
+/// domain: `steamcommunity` for `steamcommunity.com`, `nitro-gift` for
+/// `free.nitro-gift.ru`, `x` for `x.co.uk`.
+#[inline]
+fn brand_label(domain: &str) -> Option<&str> {
+    psl::domain_str(domain)?.split('.').next()
+}
+
+/// Reduce a label to the ASCII letters it looks like, so visually identical
+/// labels compare equal.
+fn skeleton(label: &str) -> String {
+    let mut mapped: String = label
+        .chars()
+        .flat_map(char::to_lowercase)
+        .map(|c| {
+            HOMOGLYPHS
+                .iter()
+                .find(|(from, _)| *from == c)
+                .map(|(_, to)| *to)
+                .unwrap_or(c)
+        })
+        .collect();
+
+    for (from, to) in DIGRAPHS {
+        mapped = mapped.replace(from, to);
+    }
+
+    mapped
+}
+
+/// Levenshtein distance between two strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut curr = vec![0; b.len() + 1];
+
+    for i in 1..=a.len() {
+        curr[0] = i;
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            curr[j] = (prev[j] + 1).min(curr[j - 1] + 1).min(prev[j - 1] + cost);
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+
+    prev[b.len()]
+}
+
+/// Whether a label is close enough to a brand label to be mistaken for it.
+///
+/// Equal skeletons only count when the label actually swaps in confusable
+/// characters, so the brand's own name on another site (`discord.me`,
+/// `discord-bots.net`) passes. Plain typos are only counted for long brands,
+/// one edit per eight characters, so ordinary words a letter away from a
+/// short brand (`discard`) pass.
+fn looks_like(label: &str, brand: &str) -> bool {
+    let candidate = skeleton(&label.replace('-', ""));
+    let target = skeleton(brand);
+
+    if candidate == target {
+        return label != brand;
+    }
+
+    let max_distance = target.chars().count() / 8;
+    max_distance > 0 && edit_distance(&candidate, &target) <= max_distance
+}
+
+/// Whether `host` imitates one of the protected domains without belonging to it.
+///
+/// The host's label is compared against each protected brand label, both
+/// whole (`dlscord.com`, `steamcomrnunity.com`, `dis-cord.com`) and per
+/// hyphenated part (`dlscord-nitro.gift`).
+fn is_lookalike(host: &str, protected: &[String]) -> bool {
+    // IDN hosts arrive punycode-encoded; compare what the user actually sees
+    let (unicode, _) = idna::domain_to_unicode(host);
+
+    if protected
+        .iter()
+        .any(|domain| is_same_or_subdomain(host, domain) || is_same_or_subdomain(&unicode, domain))
+    {
+        return false;
+    }
+
+    let Some(label) = brand_label(&unicode) else {
+        return false;
+    };
+
+    let label = label.to_lowercase();
+    let parts: Vec<&str> = label.split('-').filter(|p| !p.is_empty()).collect();
+
+    protected
+        .iter()
+        .filter_map(|domain| brand_label(domain))
+        .any(|brand| {
+            looks_like(&label, brand)
+                || (parts.len() > 1 && parts.iter().any(|part| looks_like(part, brand)))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn protected() -> Vec<String> {
+        PROTECTED_DOMAINS.iter().map(|d| d.to_string()).collect()
+    }
+
+    #[test]
+    fn skeleton_maps_confusables() {
+        assert_eq!(skeleton("dlscord"), "discord");
+        assert_eq!(skeleton("d1sc0rd"), "discord");
+        assert_eq!(skeleton("disсord"), "discord"); // Cyrillic с
+        assert_eq!(skeleton("steamcomrnunity"), "steamcommunity");
+        assert_eq!(skeleton("DISCORD"), "discord");
+    }
+
+    #[test]
+    fn edit_distance_counts_edits() {
+        assert_eq!(edit_distance("discord", "discord"), 0);
+        assert_eq!(edit_distance("discord", "discard"), 1);
+        assert_eq!(edit_distance("discord", "disord"), 1);
+        assert_eq!(edit_distance("discord", "discords"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn looks_like_needs_confusables_for_exact_matches() {
+        assert!(looks_like("dlscord", "discord"));
+        assert!(looks_like("dis-cord", "discord"));
+        assert!(!looks_like("discord", "discord"));
+        assert!(!looks_like("discard", "discord"));
+    }
+
+    #[test]
+    fn looks_like_allows_typos_of_long_brands() {
+        assert!(looks_like("steamcommunlty", "steamcommunity"));
+        assert!(looks_like("steamcomunity", "steamcommunity"));
+        assert!(!looks_like("steamcharts", "steamcommunity"));
+    }
+
+    #[test]
+    fn brand_label_uses_registrable_domain() {
+        assert_eq!(brand_label("steamcommunity.com"), Some("steamcommunity"));
+        assert_eq!(brand_label("free.nitro-gift.ru"), Some("nitro-gift"));
+        assert_eq!(brand_label("x.co.uk"), Some("x"));
+        assert_eq!(brand_label("shop.y.com.br"), Some("y"));
+    }
+
+    #[test]
+    fn lookalikes_are_flagged() {
+        let protected = protected();
+        for host in [
+            "dlscord.com",
+            "d1scord.gift",
+            "dlscord-nitro.gift",
+            "steamcomrnunity.com",
+            "steamcommunlty.ru",
+            "dlscord.co.uk",
+            "xn--dscord-pvf.com", // dіscord with a Cyrillic і
+        ] {
+            assert!(is_lookalike(host, &protected), "{host} not flagged");
+        }
+    }
+
+    #[test]
+    fn legitimate_hosts_pass() {
+        let protected = protected();
+        for host in [
+            "discord.com",
+            "cdn.discordapp.com",
+            "discord.me",
+            "discord.io",
+            "discord-bots.net",
+            "discords.com",
+            "discard.app",
+            "steamcharts.com",
+            "example.co.uk",
+            "github.com",
+        ] {
+            assert!(!is_lookalike(host, &protected), "{host} flagged");
+        }
+    }
+
+    #[test]
+    fn guild_domains_are_protected() {
+        let mut protected = protected();
+        protected.push("example.org".to_string());
+
+        assert!(is_lookalike("examp1e.org", &protected));
+        assert!(!is_lookalike("example.org", &protected));
+    }
+}
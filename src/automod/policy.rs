@@ -0,0 +1,74 @@
+use bm_lib::{
+    discord::Id,
+    model::automod::{AutomodSettings, RolePolicy},
+};
+
+/// Whether a policy applies to a member. Every condition the policy sets
+/// has to hold:
+///
+/// - `roles`: the member has at least one of them
+/// - `without_roles`: the member has none of them
+/// - `joined_within`: the member joined less than this many ms ago
+fn policy_matches(policy: &RolePolicy, roles: &[Id], joined_at: Option<i64>, now: i64) -> bool {
+    if !policy.roles.is_empty() && !policy.roles.iter().any(|role| roles.contains(role)) {
+        return false;
+    }
+
+    if policy.without_roles.iter().any(|role| roles.contains(role)) {
+        return false;
+    }
+
+    if let Some(window) = policy.joined_within {
+        // Unknown join time never counts as a recent join
+        match joined_at {
+            Some(joined_at) if now - joined_at < window as i64 => {}
+            _ => return false,
+        }
+    }
+
+    true
+}
+
+/// Layer the matching role policies on top of the resolved channel settings.
+///
+/// Precedence, lowest to highest: global settings, channel or inherited
+/// override, then each matching policy in the order configured. A policy
+/// replaces censors of the types it defines and its spam filter replaces the
+/// channel one; anything it leaves unset is kept. Returns the names of the
+/// policies applied.
+pub(super) fn apply_policies(
+    settings: &mut AutomodSettings,
+    policies: &[RolePolicy],
+    roles: &[Id],
+    joined_at: Option<i64>,
+) -> Vec<String> {
+    let now = chrono::Utc::now().timestamp_millis();
+    let mut applied = Vec::new();
+
+    for policy in policies
+        .iter()
+        .filter(|policy| policy.enabled && policy_matches(policy, roles, joined_at, now))
+    {
+        if let Some(policy_censors) = &policy.censors {
+            settings
+                .censors
+                .get_or_insert_with(Default::default)
+                .extend(policy_censors.clone());
+        }
+
+        if let Some(spam) = &policy.spam {
+            settings.spam = Some(spam.clone());
+        }
+
+        applied.push(policy.name.clone());
+    }
+
+    applied
+}
+
+/// Whether any policy needs the member's join time, which costs a lookup.
+pub(super) fn needs_join_time(policies: &[RolePolicy]) -> bool {
+    policies
+        .iter()
+        .any(|policy| policy.enabled && policy.joined_within.is_some())
+}
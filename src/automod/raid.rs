@@ -0,0 +1,389 @@
+use std::{borrow::Cow, sync::Arc, time::Duration};
+
+use bm_lib::{
+    discord::{DiscordError, DiscordResult, Id},
+    emojis::Emoji,
+    model::{
+        automod::{RaidAction, RaidSettings},
+        logging::LogEvent,
+        Config,
+    },
+};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::handler::EventHandler;
+
+/// How often active raid modes are checked for an elapsed cool-down.
+const RAID_WATCH_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Discord's "High" verification level: members must have been in the server
+/// for 10 minutes before they can talk.
+const RAISED_VERIFICATION_LEVEL: u8 = 3;
+
+/// Guilds that currently have raid mode on, scanned by the watcher.
+const RAID_GUILDS_KEY: &str = "raid_guilds";
+
+/// Turn raid mode on unless it already is, so only one shard or command
+/// starts it. State and guild are serialized by the caller the way the cache
+/// serializes values, so the plain cache getters can read them back.
+///
+/// `KEYS[1]` is the raid state, `KEYS[2]` the raid guild index. `ARGV[1]` is
+/// the serialized state, `ARGV[2]` the serialized guild.
+const CLAIM_START_SCRIPT: &str = r#"
+if redis.call('SET', KEYS[1], ARGV[1], 'NX') then
+    redis.call('SADD', KEYS[2], ARGV[2])
+    return 1
+end
+return 0
+"#;
+
+/// Replace the raid state only while raid mode is still on, so a late join
+/// can't turn it back on after it ended.
+///
+/// `KEYS[1]` is the raid state, `ARGV[1]` the serialized state.
+const UPDATE_STATE_SCRIPT: &str = r#"
+if redis.call('SET', KEYS[1], ARGV[1], 'XX') then
+    return 1
+end
+return 0
+"#;
+
+/// Turn raid mode off and return the state it had. Only the caller that gets
+/// the state back ends it, so the watcher on every shard and a moderator can
+/// race safely.
+///
+/// `KEYS[1]` is the raid state, `KEYS[2]` the raid guild index. `ARGV[1]` is
+/// the serialized guild.
+const CLAIM_END_SCRIPT: &str = r#"
+local value = redis.call('GET', KEYS[1])
+if value then
+    redis.call('DEL', KEYS[1])
+end
+redis.call('SREM', KEYS[2], ARGV[1])
+return value
+"#;
+
+#[inline]
+fn raid_joins_cache_key(guild_id: &Id) -> String {
+    format!("raid_joins:{}", guild_id)
+}
+
+#[inline]
+fn raid_state_cache_key(guild_id: &Id) -> String {
+    format!("raid_mode:{}", guild_id)
+}
+
+/// Raid mode as stored in Redis while it is on.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RaidState {
+    /// When raid mode was turned on, in ms since the epoch.
+    pub started_at: i64,
+    /// When raid mode turns itself off, in ms since the epoch. `None` when it
+    /// was turned on manually and only turns off manually.
+    pub until: Option<i64>,
+    /// Verification level to restore, set when it was raised.
+    pub previous_verification: Option<u8>,
+}
+
+fn encode(state: &RaidState) -> DiscordResult<String> {
+    serde_json::to_string(state)
+        .map_err(|e| DiscordError::ParseError(format!("Failed to encode raid state: {e}")))
+}
+
+fn encode_guild(guild_id: &Id) -> DiscordResult<String> {
+    serde_json::to_string(guild_id)
+        .map_err(|e| DiscordError::ParseError(format!("Failed to encode raid guild: {e}")))
+}
+
+fn decode(value: Option<String>) -> Option<RaidState> {
+    value.and_then(|value| serde_json::from_str(&value).ok())
+}
+
+fn raid_settings(config: &Config) -> Option<&RaidSettings> {
+    config
+        .automod
+        .as_ref()
+        .and_then(|automod| automod.raid.as_ref())
+}
+
+impl EventHandler {
+    #[instrument(skip(self))]
+    pub async fn get_raid_state(&self, guild_id: &Id) -> DiscordResult<Option<RaidState>> {
+        Ok(self
+            .cache
+            .get::<String, RaidState>(&raid_state_cache_key(guild_id))
+            .await?)
+    }
+
+    /// Store new raid state if raid mode is still on.
+    async fn update_raid_state(&self, guild_id: &Id, state: &RaidState) -> DiscordResult<()> {
+        self.cache
+            .eval::<u64>(
+                UPDATE_STATE_SCRIPT,
+                &[&raid_state_cache_key(guild_id)],
+                &[encode(state)?],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Count a join towards the raid window and act on the joiner when raid
+    /// mode is on. Returns whether the member was removed or quarantined.
+    #[instrument(skip(self, config))]
+    pub async fn handle_raid_join(
+        &self,
+        config: &Config,
+        guild_id: &Id,
+        user_id: &Id,
+    ) -> DiscordResult<bool> {
+        let Some(settings) = raid_settings(config).filter(|raid| raid.enabled) else {
+            return Ok(false);
+        };
+
+        let key = raid_joins_cache_key(guild_id);
+        let joins = self
+            .update_redis_counter(&key, settings.join_interval)
+            .await?;
+
+        match self.get_raid_state(guild_id).await? {
+            Some(mut state) => {
+                // Keep raid mode on while joins keep coming
+                if state.until.is_some() {
+                    state.until =
+                        Some(chrono::Utc::now().timestamp_millis() + settings.cooldown as i64);
+                    self.update_raid_state(guild_id, &state).await?;
+                }
+            }
+            None if joins >= settings.join_threshold => {
+                self.start_raid_mode(config, guild_id, None, Some(joins))
+                    .await?;
+            }
+            None => return Ok(false),
+        }
+
+        self.act_on_raid_joiner(config, settings, guild_id, user_id)
+            .await
+    }
+
+    async fn act_on_raid_joiner(
+        &self,
+        config: &Config,
+        settings: &RaidSettings,
+        guild_id: &Id,
+        user_id: &Id,
+    ) -> DiscordResult<bool> {
+        let Some(bot_id) = self.bot_id.get() else {
+            return Ok(false);
+        };
+
+        let reason = Some(Cow::Borrowed("Joined during raid mode"));
+
+        match settings.action {
+            RaidAction::None => Ok(false),
+            RaidAction::Kick => {
+                self.kick_user(guild_id, user_id, bot_id, reason).await?;
+                Ok(true)
+            }
+            RaidAction::Quarantine => {
+                let Some(quarantine_role) = &config.quarantine_role else {
+                    tracing::warn!("Raid quarantine configured without a quarantine role");
+                    return Ok(false);
+                };
+
+                self.quarantine_user(guild_id, user_id, bot_id, quarantine_role, None, reason)
+                    .await?;
+                Ok(true)
+            }
+        }
+    }
+
+    /// Turn raid mode on. `moderator_id` is set when turned on by hand, in
+    /// which case there is no automatic cool-down. Returns `false` when raid
+    /// mode was already on.
+    #[instrument(skip(self, config))]
+    pub async fn start_raid_mode(
+        &self,
+        config: &Config,
+        guild_id: &Id,
+        moderator_id: Option<Id>,
+        joins: Option<u64>,
+    ) -> DiscordResult<bool> {
+        let settings = raid_settings(config);
+        let now = chrono::Utc::now().timestamp_millis();
+
+        let mut state = RaidState {
+            started_at: now,
+            until: match (moderator_id, settings) {
+                (None, Some(settings)) => Some(now + settings.cooldown as i64),
+                _ => None,
+            },
+            previous_verification: None,
+        };
+
+        let claimed = self
+            .cache
+            .eval::<u64>(
+                CLAIM_START_SCRIPT,
+                &[&raid_state_cache_key(guild_id), RAID_GUILDS_KEY],
+                &[encode(&state)?, encode_guild(guild_id)?],
+            )
+            .await?;
+        if claimed == 0 {
+            return Ok(false);
+        }
+
+        if settings.is_some_and(|raid| raid.raise_verification) {
+            let guild = self.get_guild(guild_id).await?;
+            if guild.verification_level < RAISED_VERIFICATION_LEVEL {
+                match self
+                    .rest
+                    .modify_guild_verification_level(
+                        guild_id,
+                        RAISED_VERIFICATION_LEVEL,
+                        Some(Cow::Borrowed("Raid mode enabled")),
+                    )
+                    .await
+                {
+                    Ok(()) => {
+                        state.previous_verification = Some(guild.verification_level);
+                        self.update_raid_state(guild_id, &state).await?;
+                    }
+                    Err(e) => tracing::warn!(error = ?e, "failed to raise verification level"),
+                }
+            }
+        }
+
+        let description = match (moderator_id, joins) {
+            (Some(moderator_id), _) => format!("enabled by <@{moderator_id}>"),
+            (None, Some(joins)) => format!("enabled automatically after {joins} joins"),
+            (None, None) => "enabled".to_string(),
+        };
+        self.send_raid_alert(config, &format!("{} Raid mode {description}", Emoji::Cross))
+            .await;
+
+        let _ = self
+            .log_event(LogEvent::RaidModeStart {
+                guild_id: *guild_id,
+                moderator_id,
+                joins,
+            })
+            .await;
+
+        Ok(true)
+    }
+
+    /// Turn raid mode off and restore the verification level. Returns `false`
+    /// when raid mode was not on.
+    #[instrument(skip(self, config))]
+    pub async fn end_raid_mode(
+        &self,
+        config: &Config,
+        guild_id: &Id,
+        moderator_id: Option<Id>,
+    ) -> DiscordResult<bool> {
+        let value = self
+            .cache
+            .eval::<Option<String>>(
+                CLAIM_END_SCRIPT,
+                &[&raid_state_cache_key(guild_id), RAID_GUILDS_KEY],
+                &[encode_guild(guild_id)?],
+            )
+            .await?;
+        let Some(state) = decode(value) else {
+            return Ok(false);
+        };
+
+        if let Some(level) = state.previous_verification {
+            if let Err(e) = self
+                .rest
+                .modify_guild_verification_level(
+                    guild_id,
+                    level,
+                    Some(Cow::Borrowed("Raid mode disabled")),
+                )
+                .await
+            {
+                tracing::warn!(error = ?e, "failed to restore verification level");
+            }
+        }
+
+        let description = match moderator_id {
+            Some(moderator_id) => format!("disabled by <@{moderator_id}>"),
+            None => "disabled after the cool-down".to_string(),
+        };
+        self.send_raid_alert(config, &format!("{} Raid mode {description}", Emoji::Check))
+            .await;
+
+        let _ = self
+            .log_event(LogEvent::RaidModeEnd {
+                guild_id: *guild_id,
+                moderator_id,
+            })
+            .await;
+
+        Ok(true)
+    }
+
+    async fn send_raid_alert(&self, config: &Config, text: &str) {
+        let Some(settings) = raid_settings(config) else {
+            return;
+        };
+
+        let Some(channel_id) = settings.alert_channel.or(config.log_channel) else {
+            return;
+        };
+
+        let mentions = settings
+            .alert_roles
+            .iter()
+            .map(|role| format!("<@&{role}>"))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let content = if mentions.is_empty() {
+            text.to_string()
+        } else {
+            format!("{mentions} {text}")
+        };
+
+        self.rest
+            .create_message_and_forget(&channel_id, &content)
+            .await;
+    }
+
+    /// Turn off raid modes whose cool-down has elapsed. Every shard runs this,
+    /// ending raid mode is claimed atomically so each one ends only once.
+    pub fn spawn_raid_watcher(self: &Arc<Self>) {
+        let handler = Arc::clone(self);
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(RAID_WATCH_INTERVAL).await;
+                if let Err(e) = handler.expire_raid_modes().await {
+                    tracing::error!(error = ?e, "raid watcher failed");
+                }
+            }
+        });
+    }
+
+    async fn expire_raid_modes(&self) -> DiscordResult<()> {
+        let now = chrono::Utc::now().timestamp_millis();
+        let guilds: Vec<Id> = self.cache.smembers(RAID_GUILDS_KEY).await?;
+
+        for guild_id in guilds {
+            let expired = match self.get_raid_state(&guild_id).await? {
+                Some(state) => state.until.is_some_and(|until| until <= now),
+                // Stale index entry
+                None => true,
+            };
+
+            if expired {
+                let config = self.get_config(&guild_id).await?;
+                self.end_raid_mode(&config, &guild_id, None).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
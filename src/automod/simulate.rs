@@ -0,0 +1,120 @@
+use bm_lib::{
+    discord::{DiscordResult, Id},
+    model::{
+        automod::{CensorType, SpamType},
+        Config, InfractionType,
+    },
+};
+use tracing::instrument;
+
+use crate::handler::EventHandler;
+
+use super::{
+    censor::{censor_action, escalation_window},
+    SettingsSource,
+};
+
+/// Outcome of running automod against arbitrary text without acting on it.
+#[derive(Debug, Default)]
+pub struct AutomodSimulation {
+    /// Which settings applied, `None` when automod is inactive for the channel.
+    pub source: Option<SettingsSource>,
+    pub settings_name: Option<String>,
+    /// Role policies applied on top of the settings for the member.
+    pub policies: Vec<String>,
+    /// The member bypasses automod in the channel, nothing is checked.
+    pub bypassed: bool,
+    /// The censor that matched first, in the same order as live automod.
+    pub censor: Option<CensorType>,
+    pub offending_content: Option<String>,
+    /// Action the matching censor would have taken, with its duration in ms.
+    pub action: Option<(InfractionType, i64)>,
+    /// The violation this message would be for the member, when the matching
+    /// censor escalates.
+    pub violations: Option<u64>,
+    /// The matching censor is in log-only mode.
+    pub log_only: bool,
+    /// Spam filters that apply to the member. These depend on message history
+    /// and are reported but never evaluated.
+    pub spam_filters: Vec<SpamType>,
+}
+
+impl EventHandler {
+    /// Run the live settings resolution and censor checks against `content`
+    /// as if `user_id` had posted it in `channel_id`, with their role policies
+    /// and bypass rules applied.
+    ///
+    /// Nothing is deleted, no infraction is created and no counter is touched.
+    /// Escalating censors report the rung the member's current violation count
+    /// would reach.
+    #[instrument(skip(self, config, roles, content))]
+    pub async fn simulate_automod(
+        &self,
+        config: &Config,
+        guild_id: &Id,
+        channel_id: &Id,
+        user_id: &Id,
+        roles: &[Id],
+        content: &str,
+    ) -> DiscordResult<AutomodSimulation> {
+        let mut simulation = AutomodSimulation::default();
+
+        let Some((settings, source, policies)) = self
+            .resolve_member_settings(config, guild_id, channel_id, user_id, roles)
+            .await
+        else {
+            return Ok(simulation);
+        };
+
+        simulation.source = Some(source);
+        simulation.settings_name = Some(settings.name.clone());
+        simulation.policies = policies;
+
+        if self
+            .is_automod_bypassed(config, user_id, roles, settings.bypass.as_ref())
+            .await
+        {
+            simulation.bypassed = true;
+            return Ok(simulation);
+        }
+
+        if let Some(censors) = &settings.censors {
+            let word_censor = censors.get(&CensorType::Word);
+            for (typ, censor) in censors {
+                let Some(found_filter) = self
+                    .evaluate_censor(guild_id, content, &[], typ, censor, word_censor)
+                    .await
+                else {
+                    continue;
+                };
+
+                let violations = match escalation_window(typ, censor) {
+                    Some(window) => Some(
+                        self.peek_censor_violation_counter(guild_id, user_id, typ, window)
+                            .await?
+                            + 1,
+                    ),
+                    None => None,
+                };
+
+                simulation.censor = Some(typ.clone());
+                simulation.offending_content = found_filter;
+                simulation.action = Some(censor_action(typ, censor, violations));
+                simulation.violations = violations;
+                simulation.log_only = censor.log_only;
+                break;
+            }
+        }
+
+        if let Some(spam) = settings.spam.as_ref().filter(|spam| spam.enabled) {
+            if !self
+                .is_automod_bypassed(config, user_id, roles, spam.bypass.as_ref())
+                .await
+            {
+                simulation.spam_filters = spam.filters.keys().cloned().collect();
+            }
+        }
+
+        Ok(simulation)
+    }
+}
@@ -6,11 +6,49 @@ use bm_lib::{
         Infraction,
     },
 };
-use std::time::Duration;
 use tracing::instrument;
 
 use super::AutomodResult;
 
+/// Sliding-window counter.
+///
+/// `KEYS[1]` is the sorted set of hits, `KEYS[2]` a sequence used to make every
+/// member unique so hits landing in the same millisecond are all counted.
+/// `ARGV[1]` is the current time in ms, `ARGV[2]` the window length in ms.
+const SLIDING_WINDOW_SCRIPT: &str = r#"
+local now = tonumber(ARGV[1])
+local window = tonumber(ARGV[2])
+local seq = redis.call('INCR', KEYS[2])
+redis.call('ZADD', KEYS[1], now, now .. '-' .. seq)
+redis.call('ZREMRANGEBYSCORE', KEYS[1], '-inf', now - window)
+redis.call('PEXPIRE', KEYS[1], window)
+redis.call('PEXPIRE', KEYS[2], window)
+return redis.call('ZCARD', KEYS[1])
+"#;
+
+/// Read-only count of a sliding-window counter, the hits that would still be
+/// inside the window if one were recorded now.
+///
+/// `KEYS[1]` is the sorted set of hits. `ARGV[1]` is the current time in ms,
+/// `ARGV[2]` the window length in ms.
+const SLIDING_WINDOW_PEEK_SCRIPT: &str = r#"
+local now = tonumber(ARGV[1])
+local window = tonumber(ARGV[2])
+return redis.call('ZCOUNT', KEYS[1], '(' .. (now - window), '+inf')
+"#;
+
+/// Window for spam filters that set none. Counting over a zero window would
+/// drop every hit as soon as it was added and never trigger.
+const DEFAULT_SPAM_INTERVAL_MS: u64 = 5_000;
+
+#[inline]
+fn interval_ms(interval: &SpamInterval) -> u64 {
+    match interval.interval {
+        0 => DEFAULT_SPAM_INTERVAL_MS,
+        interval => interval,
+    }
+}
+
 impl EventHandler {
     #[instrument(skip(self, ctx), fields(guild_id = %ctx.guild_id, user_id = %ctx.user.id, message_id = %ctx.message.id, channel_id = %ctx.channel_id))]
     pub(crate) async fn handle_spam(
@@ -52,13 +90,20 @@ impl EventHandler {
                 continue;
             }
 
-            self.rest
-                .delete_message_and_forget(ctx.channel_id, &ctx.message.id)
-                .await;
+            if !filter.log_only {
+                self.rest
+                    .delete_message_and_forget(ctx.channel_id, &ctx.message.id)
+                    .await;
+            }
 
-            let violations = self
-                .update_violation_counter(ctx, spam_type, interval)
-                .await?;
+            // Log-only filters report what a first offence would do without
+            // counting towards the action ladder
+            let violations = if filter.log_only {
+                min_threshold
+            } else {
+                self.update_violation_counter(ctx, spam_type, interval)
+                    .await?
+            };
 
             if let Some(action) = filter
                 .action
@@ -69,21 +114,24 @@ impl EventHandler {
                 let expires_at =
                     chrono::Utc::now() + chrono::Duration::milliseconds(action.duration);
 
-                return Ok(Some(AutomodResult::new(Infraction::new_automod(
-                    *ctx.guild_id,
-                    ctx.user.id,
-                    ctx.user.id,
-                    action.action.clone(),
-                    Some(expires_at.timestamp() as u64),
-                    AutomodOffense {
-                        typ: OffenseType::Spam(spam_type.clone()),
-                        message: message_content.clone(),
-                        count: Some(count),
-                        interval: Some(interval.interval),
-                        offending_filter: None,
-                    },
-                    true,
-                ))));
+                return Ok(Some(AutomodResult::new(
+                    Infraction::new_automod(
+                        *ctx.guild_id,
+                        ctx.user.id,
+                        ctx.user.id,
+                        action.action.clone(),
+                        Some(expires_at.timestamp() as u64),
+                        AutomodOffense {
+                            typ: OffenseType::Spam(spam_type.clone()),
+                            message: message_content.clone(),
+                            count: Some(count),
+                            interval: Some(interval_ms(interval)),
+                            offending_filter: None,
+                        },
+                        true,
+                    ),
+                    filter.log_only,
+                )));
             }
         }
 
@@ -102,7 +150,7 @@ impl EventHandler {
             "spam_violations:{}:{}:{:?}",
             ctx.guild_id, ctx.user.id, spam_type
         );
-        self.update_redis_counter(&key, interval.interval).await
+        self.update_redis_counter(&key, interval_ms(interval)).await
     }
 
     #[instrument(skip(self, ctx), fields(guild_id = %ctx.guild_id, channel_id = %ctx.channel_id))]
@@ -114,22 +162,191 @@ impl EventHandler {
         interval: &SpamInterval,
     ) -> DiscordResult<u64> {
         let key = format!("spam:{}:{}:{:?}", ctx.guild_id, ctx.channel_id, spam_type);
-        self.update_redis_counter(&key, interval.interval).await
+        self.update_redis_counter(&key, interval_ms(interval)).await
     }
 
+    /// Record one hit in a sliding-window counter and return the number of hits
+    /// still inside the window. Runs as a single server-side script so the
+    /// add/trim/expire/count sequence costs one round trip and cannot interleave
+    /// with another shard updating the same key.
+    #[instrument(skip(self))]
     #[inline]
-    async fn update_redis_counter(&self, key: &str, interval_ms: u64) -> DiscordResult<u64> {
-        let now = chrono::Utc::now().timestamp_millis() as f64;
-        let window_start = now - interval_ms as f64;
-        let now_str = now.to_string();
+    pub(super) async fn update_redis_counter(
+        &self,
+        key: &str,
+        interval_ms: u64,
+    ) -> DiscordResult<u64> {
+        let now = chrono::Utc::now().timestamp_millis();
+        let seq_key = format!("{key}:seq");
 
-        self.cache.zadd(&key, now, &now_str).await?;
-        self.cache
-            .expire(&key, Duration::from_millis(interval_ms))
+        let count = self
+            .cache
+            .eval::<u64>(
+                SLIDING_WINDOW_SCRIPT,
+                &[key, &seq_key],
+                &[now.to_string(), interval_ms.to_string()],
+            )
             .await?;
-        self.cache.zremrangebyscore(&key, 0.0, window_start).await?;
 
-        let count = self.cache.zcard(&key).await?;
         Ok(count)
     }
+
+    /// Number of hits inside the window of a counter kept by
+    /// [`Self::update_redis_counter`], without recording a new one.
+    #[instrument(skip(self))]
+    #[inline]
+    pub(super) async fn peek_redis_counter(
+        &self,
+        key: &str,
+        interval_ms: u64,
+    ) -> DiscordResult<u64> {
+        let now = chrono::Utc::now().timestamp_millis();
+
+        let count = self
+            .cache
+            .eval::<u64>(
+                SLIDING_WINDOW_PEEK_SCRIPT,
+                &[key],
+                &[now.to_string(), interval_ms.to_string()],
+            )
+            .await?;
+
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future::Future,
+        sync::Arc,
+        time::{Duration, Instant},
+    };
+
+    use bm_lib::cache::{Cache, RedisCache};
+
+    use super::SLIDING_WINDOW_SCRIPT;
+
+    const HITS: usize = 10_000;
+    const TASKS: usize = 16;
+    const WINDOW_MS: u64 = 60_000;
+
+    /// Current path: one script call.
+    async fn hit(cache: Arc<Cache<RedisCache>>, key: String) -> u64 {
+        let now = chrono::Utc::now().timestamp_millis();
+        cache
+            .eval::<u64>(
+                SLIDING_WINDOW_SCRIPT,
+                &[&key, &format!("{key}:seq")],
+                &[now.to_string(), WINDOW_MS.to_string()],
+            )
+            .await
+            .expect("script failed")
+    }
+
+    /// Previous path: four round trips, with the timestamp as the member so
+    /// hits in the same millisecond collapse into one.
+    async fn hit_round_trips(cache: Arc<Cache<RedisCache>>, key: String) -> u64 {
+        let now = chrono::Utc::now().timestamp_millis() as f64;
+        let now_str = now.to_string();
+
+        cache.zadd(&key, now, &now_str).await.expect("zadd failed");
+        cache
+            .expire(&key, Duration::from_millis(WINDOW_MS))
+            .await
+            .expect("expire failed");
+        cache
+            .zremrangebyscore(&key, 0.0, now - WINDOW_MS as f64)
+            .await
+            .expect("zremrangebyscore failed");
+        cache.zcard(&key).await.expect("zcard failed")
+    }
+
+    fn percentile(sorted: &[u128], p: usize) -> u128 {
+        sorted[(sorted.len() * p / 100).min(sorted.len() - 1)]
+    }
+
+    /// Time `HITS` sequential hits on one key, then `HITS` hits from `TASKS`
+    /// concurrent callers on another, and return the final count of each.
+    async fn bench<F, Fut>(name: &str, cache: &Arc<Cache<RedisCache>>, hit: F) -> (u64, u64)
+    where
+        F: Fn(Arc<Cache<RedisCache>>, String) -> Fut + Copy + Send + 'static,
+        Fut: Future<Output = u64> + Send,
+    {
+        let prefix = format!("bench:{name}:{}", chrono::Utc::now().timestamp_millis());
+
+        // One key, one caller: per-message latency while the set grows
+        let key = format!("{prefix}:sequential");
+        let mut latencies = Vec::with_capacity(HITS);
+        let started = Instant::now();
+        let mut sequential = 0;
+        for _ in 0..HITS {
+            let start = Instant::now();
+            sequential = hit(cache.clone(), key.clone()).await;
+            latencies.push(start.elapsed().as_micros());
+        }
+        let elapsed = started.elapsed();
+        latencies.sort_unstable();
+
+        println!(
+            "{name} sequential: {HITS} hits in {elapsed:?} ({:.0}/s), p50 {}us p99 {}us max {}us, counted {sequential}",
+            HITS as f64 / elapsed.as_secs_f64(),
+            percentile(&latencies, 50),
+            percentile(&latencies, 99),
+            latencies[latencies.len() - 1],
+        );
+
+        // Many callers on the same key, as when several shards count one
+        // channel
+        let key = format!("{prefix}:concurrent");
+        let started = Instant::now();
+        let tasks = (0..TASKS)
+            .map(|_| {
+                let cache = cache.clone();
+                let key = key.clone();
+                tokio::spawn(async move {
+                    for _ in 0..HITS / TASKS {
+                        hit(cache.clone(), key.clone()).await;
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for task in tasks {
+            task.await.expect("bench task panicked");
+        }
+        let elapsed = started.elapsed();
+
+        let total = HITS / TASKS * TASKS;
+        // Counting the final hit too
+        let concurrent = hit(cache.clone(), key).await;
+        println!(
+            "{name} concurrent: {TASKS} tasks, {total} hits in {elapsed:?} ({:.0}/s), counted {concurrent}",
+            total as f64 / elapsed.as_secs_f64(),
+        );
+
+        (sequential, concurrent)
+    }
+
+    /// Latency and throughput of the sliding-window script against the four
+    /// round trips it replaced.
+    ///
+    /// `REDIS_URI=redis://127.0.0.1 cargo test --release sliding_window -- --ignored --nocapture`
+    #[tokio::test]
+    #[ignore = "needs a Redis server at REDIS_URI"]
+    async fn bench_sliding_window() {
+        let uri = std::env::var("REDIS_URI").expect("REDIS_URI not set");
+        let cache = Arc::new(Cache::new(
+            RedisCache::new(uri, "black-mesa-bench".to_string())
+                .await
+                .expect("failed to connect to redis"),
+        ));
+
+        // The old path loses hits landing in the same millisecond, so only
+        // its timings are compared
+        bench("round-trips", &cache, hit_round_trips).await;
+
+        let (sequential, concurrent) = bench("script", &cache, hit).await;
+        assert_eq!(sequential, HITS as u64);
+        assert_eq!(concurrent, (HITS / TASKS * TASKS) as u64 + 1);
+    }
 }
@@ -0,0 +1,338 @@
+use bm_lib::{
+    discord::{
+        commands::{Arg, Args, Ctx},
+        DiscordResult, EmbedBuilder,
+    },
+    emojis::Emoji,
+    model::Config,
+    permissions::Permission,
+    util,
+};
+use tracing::instrument;
+
+use crate::{
+    automod::mask_word, check_permission, commands::schema, handler::EventHandler,
+    AUTHOR_COLON_THREE, SERVICE_NAME,
+};
+
+impl EventHandler {
+    #[instrument(skip(self, config, ctx), fields(guild_id = %ctx.guild_id, user_id = %ctx.user.id))]
+    pub async fn automod_command(
+        &self,
+        config: &mut Config,
+        ctx: &Ctx<'_>,
+        args: &mut Args<'_>,
+    ) -> DiscordResult<()> {
+        let subcommand = match args.pop_subcommand() {
+            Some(subcommand) => subcommand,
+            None => {
+                self.rest
+                    .create_message(
+                        ctx.channel_id,
+                        format!(
+                            "{} Missing subcommand. Try `test`, `approve` or `unapprove`",
+                            Emoji::Cross
+                        )
+                        .as_str(),
+                    )
+                    .await?;
+                return Ok(());
+            }
+        };
+
+        match subcommand {
+            "test" => self.automod_test_subcommand(config, ctx, args).await?,
+            "approve" => {
+                self.automod_approve_subcommand(config, ctx, args, true)
+                    .await?
+            }
+            "unapprove" => {
+                self.automod_approve_subcommand(config, ctx, args, false)
+                    .await?
+            }
+            _ => {
+                self.rest
+                    .create_message(
+                        ctx.channel_id,
+                        format!(
+                            "{} Invalid subcommand. Try `test`, `approve` or `unapprove`",
+                            Emoji::Cross
+                        )
+                        .as_str(),
+                    )
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Add users to or remove them from the account age allowlist.
+    #[instrument(skip(self, config, ctx))]
+    async fn automod_approve_subcommand(
+        &self,
+        config: &mut Config,
+        ctx: &Ctx<'_>,
+        args: &Args<'_>,
+        approve: bool,
+    ) -> DiscordResult<()> {
+        check_permission!(self, config, ctx, Permission::CONFIG_EDIT);
+
+        let targets = args.get_targets();
+
+        if targets.is_empty() {
+            self.missing_parameters(config, ctx, args, schema::USER_TARGET)
+                .await?;
+            return Ok(());
+        }
+
+        let Some(gate) = config
+            .automod
+            .as_mut()
+            .and_then(|automod| automod.account_age.as_mut())
+        else {
+            self.rest
+                .create_message(
+                    ctx.channel_id,
+                    format!("{} No minimum account age is configured", Emoji::Cross).as_str(),
+                )
+                .await?;
+            return Ok(());
+        };
+
+        if approve {
+            for target in &targets {
+                if !gate.allowlist.contains(target) {
+                    gate.allowlist.push(*target);
+                }
+            }
+        } else {
+            gate.allowlist.retain(|id| !targets.contains(id));
+        }
+
+        self.set_config(ctx.guild_id, config, &ctx.user.id).await?;
+
+        let mentions = targets
+            .iter()
+            .map(|id| format!("<@{}>", id))
+            .collect::<Vec<_>>()
+            .join(", ");
+        self.rest
+            .create_message_no_ping(
+                ctx.channel_id,
+                &format!(
+                    "{} {} {}",
+                    Emoji::Check,
+                    if approve {
+                        "Approved regardless of account age:"
+                    } else {
+                        "Removed account age approval for"
+                    },
+                    mentions
+                ),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Dry-run automod against the supplied text and report what would happen.
+    /// The invoking message skips automod, so it is deleted afterwards rather
+    /// than left in the channel.
+    #[instrument(skip(self, config, ctx))]
+    async fn automod_test_subcommand(
+        &self,
+        config: &Config,
+        ctx: &Ctx<'_>,
+        args: &Args<'_>,
+    ) -> DiscordResult<()> {
+        check_permission!(self, config, ctx, Permission::CONFIG_EDIT);
+
+        let result = self.automod_test_reply(config, ctx, args).await;
+
+        self.rest
+            .delete_message_and_forget(ctx.channel_id, &ctx.message.id)
+            .await;
+
+        result
+    }
+
+    async fn automod_test_reply(
+        &self,
+        config: &Config,
+        ctx: &Ctx<'_>,
+        args: &Args<'_>,
+    ) -> DiscordResult<()> {
+        let mut text_start = 0;
+
+        let channel_id = match args
+            .get(text_start)
+            .filter(|arg| !matches!(arg, Arg::User(_)))
+            .and_then(|arg| arg.as_id())
+        {
+            Some(id) => {
+                text_start += 1;
+                id
+            }
+            None => *ctx.channel_id,
+        };
+
+        let member_id = match args.get(text_start) {
+            Some(Arg::User(id)) => {
+                text_start += 1;
+                Some(*id)
+            }
+            _ => None,
+        };
+
+        let text = args
+            .raw_args()
+            .get(text_start..)
+            .unwrap_or_default()
+            .join(" ");
+
+        if text.is_empty() {
+            self.missing_parameters(config, ctx, args, schema::AUTOMOD_TEST)
+                .await?;
+            return Ok(());
+        }
+
+        // Test as the invoking member unless another one is given, staff
+        // usually bypass automod themselves
+        let (user_id, roles) = match member_id {
+            Some(user_id) => match self.get_member_roles(ctx.guild_id, &user_id).await {
+                Ok(roles) => (user_id, roles),
+                Err(_) => {
+                    self.rest
+                        .create_message(
+                            ctx.channel_id,
+                            format!("{} That user isn't a member of this server", Emoji::Cross),
+                        )
+                        .await?;
+                    return Ok(());
+                }
+            },
+            None => (ctx.user.id, ctx.roles.to_vec()),
+        };
+
+        let simulation = self
+            .simulate_automod(config, ctx.guild_id, &channel_id, &user_id, &roles, &text)
+            .await?;
+
+        let mut embed = EmbedBuilder::new()
+            .title("Automod Test")
+            .color(0xFF8C00)
+            .footer(format!("{SERVICE_NAME} by {AUTHOR_COLON_THREE}"), None)
+            .field("Channel", format!("<#{}>", channel_id), true)
+            .field("Member", format!("<@{}>", user_id), true);
+
+        if !config.automod_enabled {
+            embed = embed.description(format!(
+                "{} Automod is disabled for this server, nothing would be checked",
+                Emoji::Cross
+            ));
+        }
+
+        let Some(source) = simulation.source else {
+            embed = embed.field("Settings", "No automod settings apply", true);
+            self.rest
+                .create_message_with_embed(ctx.channel_id, &[embed.build()])
+                .await?;
+            return Ok(());
+        };
+
+        embed = embed.field(
+            "Settings",
+            format!(
+                "`{}` ({})",
+                simulation.settings_name.as_deref().unwrap_or_default(),
+                source
+            ),
+            true,
+        );
+
+        if !simulation.policies.is_empty() {
+            embed = embed.field(
+                "Role Policies",
+                simulation
+                    .policies
+                    .iter()
+                    .map(|name| format!("`{name}`"))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                true,
+            );
+        }
+
+        if simulation.bypassed {
+            embed = embed.field(
+                "Result",
+                format!("{} Member bypasses automod here", Emoji::Check),
+                false,
+            );
+            self.rest
+                .create_message_with_embed(ctx.channel_id, &[embed.build()])
+                .await?;
+            return Ok(());
+        }
+
+        embed = match (&simulation.censor, &simulation.action) {
+            (Some(censor), Some((action, duration))) => {
+                // Never repeat the match itself, the reply stays in the channel
+                let matched = simulation
+                    .offending_content
+                    .as_deref()
+                    .map(|content| format!("`{}`", mask_word(content)))
+                    .unwrap_or_else(|| "Not on the whitelist".to_string());
+
+                let duration = if *duration > 0 {
+                    format!(" for `{}`", util::format_duration(*duration as u64 / 1000))
+                } else {
+                    String::new()
+                };
+
+                let action = if simulation.log_only {
+                    format!("Log only (would {}{})", action.to_verb(), duration)
+                } else {
+                    format!("Delete message, {}{}", action.to_verb(), duration)
+                };
+
+                let embed = embed
+                    .field("Filter", censor.to_pretty_string(), true)
+                    .field("Matched", matched, true)
+                    .field("Action", action, true);
+
+                match simulation.violations {
+                    Some(count) => embed.field("Violation", format!("#{count}"), true),
+                    None => embed,
+                }
+            }
+            _ => embed.field(
+                "Result",
+                format!("{} No censor matched", Emoji::Check),
+                false,
+            ),
+        };
+
+        if !simulation.spam_filters.is_empty() {
+            let spam = simulation
+                .spam_filters
+                .iter()
+                .map(|typ| typ.to_pretty_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            embed = embed.field(
+                "Spam Filters",
+                format!("{spam} (depend on message history, not simulated)"),
+                false,
+            );
+        }
+
+        self.rest
+            .create_message_with_embed(ctx.channel_id, &[embed.build()])
+            .await?;
+
+        Ok(())
+    }
+}
@@ -1,6 +1,10 @@
 use crate::{
-    check_permission, commands::schema, get_raw_arg, handler::EventHandler, AUTHOR_COLON_THREE,
-    SERVICE_NAME,
+    check_permission,
+    commands::schema,
+    get_raw_arg,
+    handler::{audit::format_change, EventHandler},
+    logging::LogDestination,
+    AUTHOR_COLON_THREE, SERVICE_NAME,
 };
 use bm_lib::{
     discord::{
@@ -55,11 +59,99 @@ macro_rules! set_duration_config {
     };
 }
 
+/// Config history entries shown by default and at most.
+const CONFIG_HISTORY_DEFAULT: usize = 5;
+const CONFIG_HISTORY_MAX: usize = 20;
+
+/// Discord's limit for an embed field value.
+const EMBED_FIELD_MAX: usize = 1024;
+
 impl EventHandler {
+    #[instrument(skip(self, config, ctx), fields(guild_id = %ctx.guild_id, user_id = %ctx.user.id))]
+    pub async fn config_command(
+        &self,
+        config: &Config,
+        ctx: &Ctx<'_>,
+        args: &mut Args<'_>,
+    ) -> DiscordResult<()> {
+        match args.pop_subcommand() {
+            Some("history") => self.config_history_subcommand(config, ctx, args).await,
+            Some(_) => {
+                self.rest
+                    .create_message(
+                        ctx.channel_id,
+                        format!("{} Invalid subcommand. Try `history`", Emoji::Cross).as_str(),
+                    )
+                    .await?;
+                Ok(())
+            }
+            None => {
+                self.rest
+                    .create_message(
+                        ctx.channel_id,
+                        format!("{} Missing subcommand. Try `history`", Emoji::Cross).as_str(),
+                    )
+                    .await?;
+                Ok(())
+            }
+        }
+    }
+
+    #[instrument(skip(self, config, ctx))]
+    async fn config_history_subcommand(
+        &self,
+        config: &Config,
+        ctx: &Ctx<'_>,
+        args: &Args<'_>,
+    ) -> DiscordResult<()> {
+        check_permission!(self, config, ctx, Permission::CONFIG_VIEW);
+
+        let limit = args
+            .get_raw(0)
+            .and_then(|raw| raw.parse::<usize>().ok())
+            .unwrap_or(CONFIG_HISTORY_DEFAULT)
+            .clamp(1, CONFIG_HISTORY_MAX);
+
+        let history = self.get_config_history(ctx.guild_id, limit).await?;
+
+        let mut embed = EmbedBuilder::new()
+            .title("Config History")
+            .color(0xFF8C00)
+            .footer(format!("{SERVICE_NAME} by {AUTHOR_COLON_THREE}"), None);
+
+        if history.is_empty() {
+            embed = embed.description("No config changes recorded");
+        }
+
+        for entry in history {
+            let mut value = format!("<@{}> <t:{}:R>", entry.actor_id.get(), entry.timestamp);
+            for change in &entry.changes {
+                let line = format!("\n{}", format_change(change));
+                if value.len() + line.len() > EMBED_FIELD_MAX - 4 {
+                    value.push_str("\n...");
+                    break;
+                }
+                value.push_str(&line);
+            }
+
+            embed = embed.field(
+                format!("{} field(s) changed", entry.changes.len()),
+                value,
+                false,
+            );
+        }
+
+        self.rest
+            .create_message_with_embed(ctx.channel_id, &[embed.build()])
+            .await?;
+
+        Ok(())
+    }
+
     #[instrument(skip(self, config, ctx), fields(guild_id = %ctx.guild_id, user_id = %ctx.user.id))]
     pub async fn resetconfig_command(&self, config: &Config, ctx: &Ctx<'_>) -> DiscordResult<()> {
         check_permission!(self, config, ctx, Permission::CONFIG_EDIT);
-        self.reset_config(ctx.guild_id).await
+        self.reset_config(ctx.guild_id, &ctx.user.id).await
     }
 
     #[instrument(skip(self, config, ctx), fields(guild_id = %ctx.guild_id, user_id = %ctx.user.id))]
@@ -79,7 +171,7 @@ impl EventHandler {
 
         config.prefix = prefix.to_string();
 
-        self.set_config(ctx.guild_id, &config).await?;
+        self.set_config(ctx.guild_id, &config, &ctx.user.id).await?;
 
         let embed = EmbedBuilder::new()
             .title("Prefix Set")
@@ -110,11 +202,26 @@ impl EventHandler {
         match key.to_string().as_str() {
             "prefix" => config.prefix = value.to_string(),
             "mute_role" => set_id_config!(self, ctx, value, config.mute_role),
+            "quarantine_role" => set_id_config!(self, ctx, value, config.quarantine_role),
             "default_warn_duration" => {
                 set_duration_config!(self, ctx, value, config.default_warn_duration)
             }
             "log_channel" => set_id_config!(self, ctx, value, config.log_channel),
+            "log_webhook" => match value.to_string().as_str() {
+                "none" => config.log_webhook_url = None,
+                url if LogDestination::from_webhook_url(url).is_some() => {
+                    config.log_webhook_url = Some(url.to_string())
+                }
+                _ => {
+                    self.incorrect_parameter_type_embed(ctx, "text", "webhook url")
+                        .await?;
+                    return Ok(());
+                }
+            },
             "prefer_embeds" => set_bool_config!(self, ctx, value, config.prefer_embeds),
+            "message_cache_enabled" => {
+                set_bool_config!(self, ctx, value, config.message_cache_enabled)
+            }
             "inherit_discord_perms" => {
                 set_bool_config!(self, ctx, value, config.inherit_discord_perms)
             }
@@ -133,13 +240,19 @@ impl EventHandler {
             }
         }
 
-        self.set_config(ctx.guild_id, config).await?;
+        self.set_config(ctx.guild_id, config, &ctx.user.id).await?;
+
+        // Webhook URLs contain a token, don't echo them back
+        let shown = match key.to_string().as_str() {
+            "log_webhook" if config.log_webhook_url.is_some() => "<webhook>".to_string(),
+            _ => value.to_string(),
+        };
 
         let msg = format!(
             "{} Successfully updated the config key `{}` with value `{}`",
             Emoji::Check,
             key,
-            value
+            shown
         );
 
         self.rest.create_message(&ctx.channel_id, &msg).await?;
@@ -166,7 +279,7 @@ impl EventHandler {
                 Some(std::iter::once((alias.to_string(), command.to_string())).collect());
         }
 
-        self.set_config(ctx.guild_id, &config).await?;
+        self.set_config(ctx.guild_id, &config, &ctx.user.id).await?;
 
         let msg = format!(
             "{} Successfully added the alias `{}` for the command `{}`",
@@ -211,7 +324,7 @@ impl EventHandler {
             return Ok(());
         }
 
-        self.set_config(ctx.guild_id, &config).await?;
+        self.set_config(ctx.guild_id, &config, &ctx.user.id).await?;
 
         let msg = format!(
             "{} Successfully removed the alias `{}`",
@@ -74,7 +74,8 @@ impl EventHandler {
     ) -> DiscordResult<()> {
         let name = get_raw_arg!(self, config, ctx, args, 0, schema::PERMISSION_GROUP).to_string();
 
-        self.new_group(config, ctx.guild_id, &name).await?;
+        self.new_group(config, ctx.guild_id, &ctx.user.id, &name)
+            .await?;
 
         self.rest
             .create_message(
@@ -95,7 +96,8 @@ impl EventHandler {
     ) -> DiscordResult<()> {
         let name = get_raw_arg!(self, config, ctx, args, 0, schema::PERMISSION_GROUP).to_string();
 
-        self.delete_group(config, ctx.guild_id, &name).await?;
+        self.delete_group(config, ctx.guild_id, &ctx.user.id, &name)
+            .await?;
 
         self.rest
             .create_message(
@@ -233,12 +235,12 @@ impl EventHandler {
 
         let target = match is_role {
             true => {
-                self.add_role_to_group(config, ctx.guild_id, &group_name, &target_id)
+                self.add_role_to_group(config, ctx.guild_id, &ctx.user.id, &group_name, &target_id)
                     .await?;
                 format!("<@&{}>", target_id)
             }
             false => {
-                self.add_user_to_group(config, ctx.guild_id, &group_name, &target_id)
+                self.add_user_to_group(config, ctx.guild_id, &ctx.user.id, &group_name, &target_id)
                     .await?;
                 format!("<@{}>", target_id)
             }
@@ -296,13 +298,25 @@ impl EventHandler {
 
         let target = match is_role {
             true => {
-                self.remove_role_from_group(config, ctx.guild_id, &group_name, &target_id)
-                    .await?;
+                self.remove_role_from_group(
+                    config,
+                    ctx.guild_id,
+                    &ctx.user.id,
+                    &group_name,
+                    &target_id,
+                )
+                .await?;
                 format!("<@&{}>", target_id)
             }
             false => {
-                self.remove_user_from_group(config, ctx.guild_id, &group_name, &target_id)
-                    .await?;
+                self.remove_user_from_group(
+                    config,
+                    ctx.guild_id,
+                    &ctx.user.id,
+                    &group_name,
+                    &target_id,
+                )
+                .await?;
                 format!("<@{}>", target_id)
             }
         };
@@ -463,7 +477,7 @@ impl EventHandler {
             },
         );
 
-        self.grant_permissions(config, ctx.guild_id, &group_name, permissions)
+        self.grant_permissions(config, ctx.guild_id, &ctx.user.id, &group_name, permissions)
             .await?;
 
         self.rest
@@ -519,7 +533,7 @@ impl EventHandler {
             },
         );
 
-        self.revoke_permissions(config, ctx.guild_id, &group_name, permissions)
+        self.revoke_permissions(config, ctx.guild_id, &ctx.user.id, &group_name, permissions)
             .await?;
 
         self.rest
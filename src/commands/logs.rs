@@ -0,0 +1,647 @@
+use std::collections::HashMap;
+
+use bm_lib::{
+    discord::{
+        commands::{Args, Ctx},
+        DiscordResult, EmbedBuilder, Id,
+    },
+    emojis::Emoji,
+    model::{logging::LogEventType, Config, LogConfig},
+    permissions::Permission,
+};
+use tracing::instrument;
+
+use crate::{
+    check_permission, get_raw_arg, handler::EventHandler, logging::validate_log_config,
+    AUTHOR_COLON_THREE, SERVICE_NAME,
+};
+
+use super::schema;
+
+const SUBCOMMANDS: &str = "`events`, `enable`, `disable`, `channel`, `route`, `unroute`, `template`, `preview`, `reset`, `ignore`, `unignore`";
+
+/// Kinds of target that `logs ignore` accepts.
+const IGNORE_KINDS: &str = "`channel`, `user`, `role`, `bots`";
+
+/// Template fields that `logs template` can edit.
+const TEMPLATE_FIELDS: &str = "`title`, `body`, `footer`, `text`, `color`, `embed`";
+
+/// Example values used to preview templates, keyed by variable name.
+fn sample_vars(event_type: &LogEventType, ctx: &Ctx<'_>) -> HashMap<String, String> {
+    let now = chrono::Utc::now().timestamp();
+
+    event_type
+        .variables()
+        .iter()
+        .map(|name| {
+            let value = match *name {
+                "guild_id" => ctx.guild_id.to_string(),
+                "channel_id" | "from_channel_id" | "to_channel_id" => ctx.channel_id.to_string(),
+                "user_id" | "moderator_id" | "inviter_id" => ctx.user.id.to_string(),
+                "username" => ctx.user.username.to_string(),
+                "reason" => "Example reason".to_string(),
+                "duration" | "duration_secs" => "3600".to_string(),
+                "count" => "3".to_string(),
+                name if name.ends_with("_id") => ctx.message.id.to_string(),
+                name if name.ends_with("_at") || name == "timestamp" => now.to_string(),
+                name => format!("example {name}"),
+            };
+            (name.to_string(), value)
+        })
+        .collect()
+}
+
+impl EventHandler {
+    #[instrument(skip(self, config, ctx), fields(guild_id = %ctx.guild_id, user_id = %ctx.user.id))]
+    pub async fn logs_command(
+        &self,
+        config: &mut Config,
+        ctx: &Ctx<'_>,
+        args: &mut Args<'_>,
+    ) -> DiscordResult<()> {
+        let subcommand = match args.pop_subcommand() {
+            Some(subcommand) => subcommand,
+            None => {
+                self.rest
+                    .create_message(
+                        ctx.channel_id,
+                        format!("{} Missing subcommand. Try {SUBCOMMANDS}", Emoji::Cross).as_str(),
+                    )
+                    .await?;
+                return Ok(());
+            }
+        };
+
+        match subcommand {
+            "events" => self.logs_events_subcommand(config, ctx).await?,
+            "enable" => self.logs_toggle_subcommand(config, ctx, args, true).await?,
+            "disable" => {
+                self.logs_toggle_subcommand(config, ctx, args, false)
+                    .await?
+            }
+            "channel" => self.logs_channel_subcommand(config, ctx, args).await?,
+            "route" => self.logs_route_subcommand(config, ctx, args, true).await?,
+            "unroute" => self.logs_route_subcommand(config, ctx, args, false).await?,
+            "template" => self.logs_template_subcommand(config, ctx, args).await?,
+            "preview" => self.logs_preview_subcommand(config, ctx, args).await?,
+            "reset" => self.logs_reset_subcommand(config, ctx, args).await?,
+            "ignore" => self.logs_ignore_subcommand(config, ctx, args, true).await?,
+            "unignore" => {
+                self.logs_ignore_subcommand(config, ctx, args, false)
+                    .await?
+            }
+            _ => {
+                self.rest
+                    .create_message(
+                        ctx.channel_id,
+                        format!("{} Invalid subcommand. Try {SUBCOMMANDS}", Emoji::Cross).as_str(),
+                    )
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Parse the event argument, replying when it is not a known event type.
+    async fn parse_log_event(
+        &self,
+        ctx: &Ctx<'_>,
+        raw: &str,
+    ) -> DiscordResult<Option<LogEventType>> {
+        let event_type = LogEventType::from_db_key(&raw.to_lowercase());
+        if event_type.is_none() {
+            self.rest
+                .create_message(
+                    ctx.channel_id,
+                    format!(
+                        "{} Unknown event `{raw}`. See `logs events` for the list",
+                        Emoji::Cross
+                    )
+                    .as_str(),
+                )
+                .await?;
+        }
+
+        Ok(event_type)
+    }
+
+    /// The stored config for an event, or a new disabled one.
+    async fn log_config_or_default(
+        &self,
+        guild_id: &Id,
+        event_type: &LogEventType,
+    ) -> DiscordResult<LogConfig> {
+        let existing = self
+            .db
+            .get_log_config_for_event(guild_id, event_type.as_db_key())
+            .await?;
+
+        Ok(existing.unwrap_or_else(|| LogConfig::new(*guild_id, event_type.as_db_key())))
+    }
+
+    /// Validate and store a log config, replying with the error if a template
+    /// is invalid. Returns whether it was saved.
+    async fn save_log_config(&self, ctx: &Ctx<'_>, log_config: &LogConfig) -> DiscordResult<bool> {
+        if let Err(e) = validate_log_config(log_config) {
+            self.rest
+                .create_message(
+                    ctx.channel_id,
+                    format!("{} Invalid template: {e}", Emoji::Cross).as_str(),
+                )
+                .await?;
+            return Ok(false);
+        }
+
+        self.db.upsert_log_config(log_config).await?;
+
+        Ok(true)
+    }
+
+    #[instrument(skip(self, config, ctx))]
+    async fn logs_events_subcommand(&self, config: &Config, ctx: &Ctx<'_>) -> DiscordResult<()> {
+        check_permission!(self, config, ctx, Permission::CONFIG_VIEW);
+
+        let events = LogEventType::all();
+        let total_pages = (events.len() + 24) / 25;
+
+        for (page_idx, chunk) in events.chunks(25).enumerate() {
+            let mut embed = EmbedBuilder::new()
+                .title(format!(
+                    "Log Events (Page {}/{})",
+                    page_idx + 1,
+                    total_pages
+                ))
+                .description("Events that can be logged and the variables their templates can use")
+                .color(0xFF8C00)
+                .footer(format!("{SERVICE_NAME} by {AUTHOR_COLON_THREE}"), None);
+
+            for event_type in chunk {
+                let variables = event_type
+                    .variables()
+                    .iter()
+                    .map(|name| format!("`{name}`"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+                embed = embed.field(event_type.as_db_key(), variables, false);
+            }
+
+            self.rest
+                .create_message_with_embed(ctx.channel_id, &[embed.build()])
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    #[instrument(skip(self, config, ctx))]
+    async fn logs_toggle_subcommand(
+        &self,
+        config: &Config,
+        ctx: &Ctx<'_>,
+        args: &Args<'_>,
+        enabled: bool,
+    ) -> DiscordResult<()> {
+        check_permission!(self, config, ctx, Permission::CONFIG_EDIT);
+
+        let raw = get_raw_arg!(self, config, ctx, args, 0, schema::LOGS_TOGGLE);
+        let Some(event_type) = self.parse_log_event(ctx, raw).await? else {
+            return Ok(());
+        };
+
+        let mut log_config = self
+            .log_config_or_default(ctx.guild_id, &event_type)
+            .await?;
+        log_config.enabled = enabled;
+        if let Some(channel_id) = args.get(1).and_then(|a| a.as_id()) {
+            log_config.channel_id = Some(channel_id);
+        }
+
+        if !self.save_log_config(ctx, &log_config).await? {
+            return Ok(());
+        }
+
+        let destination = match log_config.channel_id.or(config.log_channel) {
+            Some(channel_id) => format!(" in <#{channel_id}>"),
+            None if enabled => " but no log channel is set".to_string(),
+            None => String::new(),
+        };
+
+        self.rest
+            .create_message(
+                ctx.channel_id,
+                format!(
+                    "{} `{}` logging {}{destination}",
+                    Emoji::Check,
+                    event_type.as_db_key(),
+                    if enabled { "enabled" } else { "disabled" }
+                )
+                .as_str(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    #[instrument(skip(self, config, ctx))]
+    async fn logs_channel_subcommand(
+        &self,
+        config: &Config,
+        ctx: &Ctx<'_>,
+        args: &Args<'_>,
+    ) -> DiscordResult<()> {
+        check_permission!(self, config, ctx, Permission::CONFIG_EDIT);
+
+        let raw = get_raw_arg!(self, config, ctx, args, 0, schema::LOGS_CHANNEL);
+        let Some(event_type) = self.parse_log_event(ctx, raw).await? else {
+            return Ok(());
+        };
+
+        let Some(channel_id) = args.get(1).and_then(|a| a.as_id()) else {
+            self.missing_parameters(config, ctx, args, schema::LOGS_CHANNEL)
+                .await?;
+            return Ok(());
+        };
+
+        let mut log_config = self
+            .log_config_or_default(ctx.guild_id, &event_type)
+            .await?;
+        log_config.channel_id = Some(channel_id);
+
+        if !self.save_log_config(ctx, &log_config).await? {
+            return Ok(());
+        }
+
+        self.rest
+            .create_message(
+                ctx.channel_id,
+                format!(
+                    "{} `{}` logs will be sent to <#{channel_id}>",
+                    Emoji::Check,
+                    event_type.as_db_key()
+                )
+                .as_str(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    #[instrument(skip(self, config, ctx))]
+    async fn logs_route_subcommand(
+        &self,
+        config: &Config,
+        ctx: &Ctx<'_>,
+        args: &Args<'_>,
+        add: bool,
+    ) -> DiscordResult<()> {
+        check_permission!(self, config, ctx, Permission::CONFIG_EDIT);
+
+        let raw = get_raw_arg!(self, config, ctx, args, 0, schema::LOGS_ROUTE);
+        let Some(event_type) = self.parse_log_event(ctx, raw).await? else {
+            return Ok(());
+        };
+
+        let channel_ids: Vec<Id> = (1..args.raw_args().len())
+            .filter_map(|i| args.get(i).and_then(|a| a.as_id()))
+            .collect();
+        if channel_ids.is_empty() {
+            self.missing_parameters(config, ctx, args, schema::LOGS_ROUTE)
+                .await?;
+            return Ok(());
+        }
+
+        let mut log_config = self
+            .log_config_or_default(ctx.guild_id, &event_type)
+            .await?;
+        for channel_id in &channel_ids {
+            if add {
+                if !log_config.routed_channel_ids.contains(channel_id) {
+                    log_config.routed_channel_ids.push(*channel_id);
+                }
+            } else {
+                log_config.routed_channel_ids.retain(|id| id != channel_id);
+            }
+        }
+
+        if !self.save_log_config(ctx, &log_config).await? {
+            return Ok(());
+        }
+
+        let routes = match log_config.routed_channel_ids.is_empty() {
+            true => "none".to_string(),
+            false => log_config
+                .routed_channel_ids
+                .iter()
+                .map(|id| format!("<#{id}>"))
+                .collect::<Vec<_>>()
+                .join(", "),
+        };
+
+        self.rest
+            .create_message(
+                ctx.channel_id,
+                format!(
+                    "{} `{}` is now also routed to: {routes}",
+                    Emoji::Check,
+                    event_type.as_db_key()
+                )
+                .as_str(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    #[instrument(skip(self, config, ctx))]
+    async fn logs_template_subcommand(
+        &self,
+        config: &Config,
+        ctx: &Ctx<'_>,
+        args: &Args<'_>,
+    ) -> DiscordResult<()> {
+        check_permission!(self, config, ctx, Permission::CONFIG_EDIT);
+
+        let raw = get_raw_arg!(self, config, ctx, args, 0, schema::LOGS_TEMPLATE);
+        let field = get_raw_arg!(self, config, ctx, args, 1, schema::LOGS_TEMPLATE);
+        let Some(event_type) = self.parse_log_event(ctx, raw).await? else {
+            return Ok(());
+        };
+
+        // Templates keep their line breaks, so take the value from the message
+        let value = template_value(&ctx.message.content, raw, field);
+        if value.is_empty() {
+            self.missing_parameters(config, ctx, args, schema::LOGS_TEMPLATE)
+                .await?;
+            return Ok(());
+        }
+
+        let mut log_config = self
+            .log_config_or_default(ctx.guild_id, &event_type)
+            .await?;
+
+        match field.to_lowercase().as_str() {
+            "title" => log_config.embed_title = Some(value.to_string()),
+            "body" => log_config.embed_body = Some(value.to_string()),
+            "footer" => log_config.embed_footer = Some(value.to_string()),
+            "text" => log_config.text_content = Some(value.to_string()),
+            "color" | "colour" => {
+                let Ok(color) = u32::from_str_radix(value.trim_start_matches('#'), 16) else {
+                    self.incorrect_parameter_type_embed(ctx, "text", "hex colour")
+                        .await?;
+                    return Ok(());
+                };
+                log_config.embed_color = Some(color);
+            }
+            "embed" => {
+                let Ok(embed) = value.parse() else {
+                    self.incorrect_parameter_type_embed(ctx, "text", "bool")
+                        .await?;
+                    return Ok(());
+                };
+                log_config.embed = embed;
+            }
+            _ => {
+                self.rest
+                    .create_message(
+                        ctx.channel_id,
+                        format!("{} Invalid field. Try {TEMPLATE_FIELDS}", Emoji::Cross).as_str(),
+                    )
+                    .await?;
+                return Ok(());
+            }
+        }
+
+        if !self.save_log_config(ctx, &log_config).await? {
+            return Ok(());
+        }
+
+        self.rest
+            .create_message(
+                ctx.channel_id,
+                format!(
+                    "{} Updated `{}` {}, preview:",
+                    Emoji::Check,
+                    event_type.as_db_key(),
+                    field.to_lowercase()
+                )
+                .as_str(),
+            )
+            .await?;
+
+        let vars = sample_vars(&event_type, ctx);
+        self.send_log_preview(ctx.channel_id, &log_config, &vars)
+            .await;
+
+        Ok(())
+    }
+
+    #[instrument(skip(self, config, ctx))]
+    async fn logs_preview_subcommand(
+        &self,
+        config: &Config,
+        ctx: &Ctx<'_>,
+        args: &Args<'_>,
+    ) -> DiscordResult<()> {
+        check_permission!(self, config, ctx, Permission::CONFIG_VIEW);
+
+        let raw = get_raw_arg!(self, config, ctx, args, 0, schema::LOGS_EVENT);
+        let Some(event_type) = self.parse_log_event(ctx, raw).await? else {
+            return Ok(());
+        };
+
+        let log_config = self
+            .log_config_or_default(ctx.guild_id, &event_type)
+            .await?;
+        let vars = sample_vars(&event_type, ctx);
+        self.send_log_preview(ctx.channel_id, &log_config, &vars)
+            .await;
+
+        Ok(())
+    }
+
+    #[instrument(skip(self, config, ctx))]
+    async fn logs_reset_subcommand(
+        &self,
+        config: &Config,
+        ctx: &Ctx<'_>,
+        args: &Args<'_>,
+    ) -> DiscordResult<()> {
+        check_permission!(self, config, ctx, Permission::CONFIG_EDIT);
+
+        let raw = get_raw_arg!(self, config, ctx, args, 0, schema::LOGS_EVENT);
+        let Some(event_type) = self.parse_log_event(ctx, raw).await? else {
+            return Ok(());
+        };
+
+        // Keep where and whether the event is logged, drop the templates
+        let current = self
+            .log_config_or_default(ctx.guild_id, &event_type)
+            .await?;
+        let mut log_config = LogConfig::new(*ctx.guild_id, event_type.as_db_key());
+        log_config.enabled = current.enabled;
+        log_config.channel_id = current.channel_id;
+        log_config.routed_channel_ids = current.routed_channel_ids;
+
+        self.db.upsert_log_config(&log_config).await?;
+
+        self.rest
+            .create_message(
+                ctx.channel_id,
+                format!(
+                    "{} `{}` templates reset to defaults",
+                    Emoji::Check,
+                    event_type.as_db_key()
+                )
+                .as_str(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    #[instrument(skip(self, config, ctx))]
+    async fn logs_ignore_subcommand(
+        &self,
+        config: &mut Config,
+        ctx: &Ctx<'_>,
+        args: &Args<'_>,
+        ignore: bool,
+    ) -> DiscordResult<()> {
+        let Some(kind) = args.get_raw(0) else {
+            check_permission!(self, config, ctx, Permission::CONFIG_VIEW);
+            return self.send_log_ignores(config, ctx).await;
+        };
+
+        check_permission!(self, config, ctx, Permission::CONFIG_EDIT);
+
+        let list = match kind.to_lowercase().as_str() {
+            "channel" => &mut config.logging_ignored_channels,
+            "user" => &mut config.logging_ignored_users,
+            "role" => &mut config.logging_ignored_roles,
+            "bots" => {
+                config.logging_ignore_bots = ignore;
+                self.set_config(ctx.guild_id, config, &ctx.user.id).await?;
+
+                self.rest
+                    .create_message(
+                        ctx.channel_id,
+                        format!(
+                            "{} Bot messages will {}be logged",
+                            Emoji::Check,
+                            if ignore { "no longer " } else { "" }
+                        )
+                        .as_str(),
+                    )
+                    .await?;
+                return Ok(());
+            }
+            _ => {
+                self.rest
+                    .create_message(
+                        ctx.channel_id,
+                        format!("{} Invalid kind. Try {IGNORE_KINDS}", Emoji::Cross).as_str(),
+                    )
+                    .await?;
+                return Ok(());
+            }
+        };
+
+        let Some(id) = args.get(1).and_then(|a| a.as_id()) else {
+            self.missing_parameters(config, ctx, args, schema::LOGS_IGNORE)
+                .await?;
+            return Ok(());
+        };
+
+        if ignore {
+            if !list.contains(&id) {
+                list.push(id);
+            }
+        } else {
+            list.retain(|existing| *existing != id);
+        }
+
+        self.set_config(ctx.guild_id, config, &ctx.user.id).await?;
+
+        self.rest
+            .create_message(
+                ctx.channel_id,
+                format!(
+                    "{} `{id}` is {} ignored in message logs",
+                    Emoji::Check,
+                    if ignore { "now" } else { "no longer" }
+                )
+                .as_str(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn send_log_ignores(&self, config: &Config, ctx: &Ctx<'_>) -> DiscordResult<()> {
+        fn list(ids: &[Id], fmt: fn(&Id) -> String) -> String {
+            match ids.is_empty() {
+                true => "None".to_string(),
+                false => ids.iter().map(fmt).collect::<Vec<_>>().join(", "),
+            }
+        }
+
+        let embed = EmbedBuilder::new()
+            .title("Message Log Ignores")
+            .color(0xFF8C00)
+            .footer(format!("{SERVICE_NAME} by {AUTHOR_COLON_THREE}"), None)
+            .field(
+                "Channels",
+                list(&config.logging_ignored_channels, |id| format!("<#{id}>")),
+                false,
+            )
+            .field(
+                "Users",
+                list(&config.logging_ignored_users, |id| format!("<@{id}>")),
+                false,
+            )
+            .field(
+                "Roles",
+                list(&config.logging_ignored_roles, |id| format!("<@&{id}>")),
+                false,
+            )
+            .field(
+                "Bots",
+                if config.logging_ignore_bots {
+                    "Ignored"
+                } else {
+                    "Logged"
+                },
+                false,
+            )
+            .build();
+
+        self.rest
+            .create_message_with_embed(ctx.channel_id, &[embed])
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Everything after `<event> <field>` in the message, with whitespace kept.
+fn template_value<'a>(content: &'a str, event: &str, field: &str) -> &'a str {
+    let mut words = content
+        .split_whitespace()
+        .map(|word| (word, word.as_ptr() as usize - content.as_ptr() as usize));
+
+    while let Some((word, _)) = words.next() {
+        if !word.eq_ignore_ascii_case(event) {
+            continue;
+        }
+
+        if let Some((next, offset)) = words.next() {
+            if next.eq_ignore_ascii_case(field) {
+                return content[offset + next.len()..].trim();
+            }
+        }
+    }
+
+    ""
+}
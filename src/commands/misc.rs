@@ -48,7 +48,7 @@ impl EventHandler {
             return Ok(());
         }
 
-        self.reset_config(ctx.guild_id).await?;
+        self.reset_config(ctx.guild_id, &ctx.user.id).await?;
 
         self.rest
             .create_message(
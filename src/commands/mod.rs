@@ -1,6 +1,8 @@
 mod audio;
+mod automod;
 mod config;
 mod groups;
+mod logs;
 mod misc;
 mod moderation;
 mod privileged;
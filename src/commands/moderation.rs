@@ -4,7 +4,10 @@ use tracing::instrument;
 use crate::{
     check_can_target, check_permission,
     commands::schema,
-    handler::{moderation::DEFAULT_WARN_LENGTH, EventHandler, ZWSP},
+    handler::{
+        moderation::{QuarantineOutcome, DEFAULT_WARN_LENGTH},
+        EventHandler, ZWSP,
+    },
     AUTHOR_COLON_THREE, SERVICE_NAME,
 };
 use bm_lib::{
@@ -16,6 +19,9 @@ use bm_lib::{
     permissions::Permission,
 };
 
+/// Inviters or invited members listed by `invites`.
+const INVITES_SHOWN: usize = 25;
+
 impl EventHandler {
     #[instrument(skip(self, config, ctx), fields(guild_id = %ctx.guild_id, user_id = %ctx.user.id))]
     pub async fn kick_command(
@@ -176,6 +182,99 @@ impl EventHandler {
         Ok(())
     }
 
+    #[instrument(skip(self, config, ctx), fields(guild_id = %ctx.guild_id, user_id = %ctx.user.id))]
+    pub async fn quarantine_command(
+        &self,
+        config: &Config,
+        ctx: &Ctx<'_>,
+        args: &Args<'_>,
+    ) -> DiscordResult<()> {
+        check_permission!(self, config, ctx, Permission::MODERATION_QUARANTINE);
+
+        let Some(quarantine_role) = config.quarantine_role.as_ref() else {
+            self.rest
+                .create_message(
+                    ctx.channel_id,
+                    format!(
+                        "{} No quarantine role configured. Set one with `setconfig quarantine_role`",
+                        bm_lib::emojis::Emoji::Cross
+                    )
+                    .as_str(),
+                )
+                .await?;
+            return Ok(());
+        };
+
+        let targets = args.get_targets();
+
+        if targets.is_empty() {
+            self.missing_parameters(config, ctx, args, schema::USER_TARGET)
+                .await?;
+            return Ok(());
+        }
+
+        check_can_target!(self, config, ctx, &targets);
+
+        let duration = args.get_first_duration();
+        let reason = args.get_first_text();
+
+        let outcomes = match try_join_all(targets.iter().map(|target| {
+            self.quarantine_user(
+                ctx.guild_id,
+                target,
+                &ctx.user.id,
+                quarantine_role,
+                duration,
+                reason.map(std::borrow::Cow::Borrowed),
+            )
+        }))
+        .await
+        {
+            Ok(v) => v,
+            Err(e) => {
+                tracing::error!("Failed to quarantine user: {}", e);
+                self.send_error(&ctx.channel_id, e).await?;
+                return Ok(());
+            }
+        };
+
+        let mut infractions = Vec::new();
+        let mut skipped = Vec::new();
+        for (target, outcome) in targets.iter().zip(outcomes) {
+            match outcome {
+                QuarantineOutcome::Created(infraction) => infractions.push(infraction),
+                QuarantineOutcome::Existing(_) => skipped.push(format!(
+                    "{} <@{}> is already quarantined",
+                    bm_lib::emojis::Emoji::Cross,
+                    target
+                )),
+                QuarantineOutcome::Pending => skipped.push(format!(
+                    "{} <@{}> is already being quarantined",
+                    bm_lib::emojis::Emoji::Cross,
+                    target
+                )),
+            }
+        }
+
+        if !infractions.is_empty() {
+            if let Err(e) = self
+                .send_infraction_channel(ctx.channel_id, &infractions, config.prefer_embeds)
+                .await
+            {
+                tracing::error!("Failed to send infraction channel message: {}", e);
+                self.send_error(&ctx.channel_id, e).await?;
+            }
+        }
+
+        if !skipped.is_empty() {
+            self.rest
+                .create_message(ctx.channel_id, skipped.join("\n").as_str())
+                .await?;
+        }
+
+        Ok(())
+    }
+
     #[instrument(skip(self, config, ctx), fields(guild_id = %ctx.guild_id, user_id = %ctx.user.id))]
     pub async fn warn_command(
         &self,
@@ -339,6 +438,185 @@ impl EventHandler {
         Ok(())
     }
 
+    #[instrument(skip(self, config, ctx), fields(guild_id = %ctx.guild_id, user_id = %ctx.user.id))]
+    pub async fn unquarantine_command(
+        &self,
+        config: &Config,
+        ctx: &Ctx<'_>,
+        args: &Args<'_>,
+    ) -> DiscordResult<()> {
+        check_permission!(self, config, ctx, Permission::MODERATION_QUARANTINE);
+
+        let targets = args.get_targets();
+
+        if targets.is_empty() {
+            self.missing_parameters(config, ctx, args, schema::USER_TARGET)
+                .await?;
+            return Ok(());
+        }
+
+        let reason = args.get_first_text();
+
+        if let Err(e) = try_join_all(targets.iter().map(|target| {
+            self.unquarantine_user(
+                ctx.guild_id,
+                target,
+                &ctx.user.id,
+                reason.map(std::borrow::Cow::Borrowed),
+            )
+        }))
+        .await
+        {
+            tracing::error!("Failed to unquarantine user: {}", e);
+            self.send_error(&ctx.channel_id, e).await?;
+            return Ok(());
+        }
+
+        let mentions = targets
+            .iter()
+            .map(|id| format!("<@{}>", id))
+            .collect::<Vec<_>>()
+            .join(", ");
+        self.rest
+            .create_message_no_ping(
+                &ctx.channel_id,
+                &format!(
+                    "{} Successfully released {} from quarantine",
+                    bm_lib::emojis::Emoji::Check,
+                    mentions
+                ),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Run the automod name filter over every member of the guild.
+    #[instrument(skip(self, config, ctx), fields(guild_id = %ctx.guild_id, user_id = %ctx.user.id))]
+    pub async fn dehoist_command(&self, config: &Config, ctx: &Ctx<'_>) -> DiscordResult<()> {
+        check_permission!(self, config, ctx, Permission::MODERATION_NICKNAME);
+
+        let name_filter_enabled = config
+            .automod
+            .as_ref()
+            .and_then(|automod| automod.names.as_ref())
+            .is_some_and(|filter| filter.enabled);
+
+        if !config.automod_enabled || !name_filter_enabled {
+            self.rest
+                .create_message(
+                    ctx.channel_id,
+                    format!(
+                        "{} The automod name filter is not enabled",
+                        bm_lib::emojis::Emoji::Cross
+                    )
+                    .as_str(),
+                )
+                .await?;
+            return Ok(());
+        }
+
+        let started = match self
+            .spawn_member_name_sweep(config.clone(), *ctx.guild_id, *ctx.channel_id)
+            .await
+        {
+            Ok(started) => started,
+            Err(e) => {
+                tracing::error!("Failed to start name sweep: {}", e);
+                self.send_error(&ctx.channel_id, e).await?;
+                return Ok(());
+            }
+        };
+
+        let message = if started {
+            format!(
+                "{} Checking member names, this can take a while on large servers",
+                bm_lib::emojis::Emoji::Check
+            )
+        } else {
+            format!(
+                "{} A name sweep is already running for this server",
+                bm_lib::emojis::Emoji::Cross
+            )
+        };
+
+        self.rest
+            .create_message(ctx.channel_id, message.as_str())
+            .await?;
+
+        Ok(())
+    }
+
+    /// Show, enable or disable raid mode.
+    #[instrument(skip(self, config, ctx), fields(guild_id = %ctx.guild_id, user_id = %ctx.user.id))]
+    pub async fn raidmode_command(
+        &self,
+        config: &Config,
+        ctx: &Ctx<'_>,
+        args: &mut Args<'_>,
+    ) -> DiscordResult<()> {
+        check_permission!(self, config, ctx, Permission::MODERATION_RAIDMODE);
+
+        let content = match args.pop_subcommand() {
+            Some("on") => {
+                // Raid mode only acts on joiners through the raid settings
+                let configured = config
+                    .automod
+                    .as_ref()
+                    .and_then(|automod| automod.raid.as_ref())
+                    .is_some_and(|raid| raid.enabled);
+
+                if !configured {
+                    format!(
+                        "{} Raid protection is not configured, raid mode would do nothing. Set up `automod.raid` first",
+                        bm_lib::emojis::Emoji::Cross
+                    )
+                } else if self
+                    .start_raid_mode(config, ctx.guild_id, Some(ctx.user.id), None)
+                    .await?
+                {
+                    format!("{} Raid mode enabled", bm_lib::emojis::Emoji::Check)
+                } else {
+                    format!("{} Raid mode is already on", bm_lib::emojis::Emoji::Cross)
+                }
+            }
+            Some("off") => {
+                if self
+                    .end_raid_mode(config, ctx.guild_id, Some(ctx.user.id))
+                    .await?
+                {
+                    format!("{} Raid mode disabled", bm_lib::emojis::Emoji::Check)
+                } else {
+                    format!("{} Raid mode is not on", bm_lib::emojis::Emoji::Cross)
+                }
+            }
+            None => match self.get_raid_state(ctx.guild_id).await? {
+                Some(state) => match state.until {
+                    Some(until) => format!(
+                        "Raid mode is on since <t:{}:R>, turning off <t:{}:R>",
+                        state.started_at / 1000,
+                        until / 1000
+                    ),
+                    None => format!(
+                        "Raid mode is on since <t:{}:R> until turned off",
+                        state.started_at / 1000
+                    ),
+                },
+                None => "Raid mode is off".to_string(),
+            },
+            Some(_) => format!(
+                "{} Invalid subcommand. Try `on` or `off`",
+                bm_lib::emojis::Emoji::Cross
+            ),
+        };
+
+        self.rest
+            .create_message(ctx.channel_id, content.as_str())
+            .await?;
+
+        Ok(())
+    }
+
     pub async fn pardon_command(
         &self,
         config: &Config,
@@ -565,4 +843,71 @@ impl EventHandler {
 
         Ok(())
     }
+
+    #[instrument(skip(self, config, ctx), fields(guild_id = %ctx.guild_id, user_id = %ctx.user.id))]
+    pub async fn invites_command(
+        &self,
+        config: &Config,
+        ctx: &Ctx<'_>,
+        args: &Args<'_>,
+    ) -> DiscordResult<()> {
+        check_permission!(self, config, ctx, Permission::MODERATION_LOOKUP);
+
+        let targets = args.get_targets();
+
+        let embed = match targets.first() {
+            Some(inviter_id) => {
+                let joins = self
+                    .get_inviter_joins(ctx.guild_id, inviter_id, INVITES_SHOWN)
+                    .await?;
+
+                let description = match joins.is_empty() {
+                    true => format!("No tracked joins from <@{}>", inviter_id.get()),
+                    false => joins
+                        .iter()
+                        .map(|id| format!("<@{}>", id.get()))
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                };
+
+                EmbedBuilder::new()
+                    .title("Invited Members")
+                    .description(format!(
+                        "Members invited by <@{}>, newest first\n\n{}",
+                        inviter_id.get(),
+                        description
+                    ))
+            }
+            None => {
+                let top = self.get_top_inviters(ctx.guild_id, INVITES_SHOWN).await?;
+
+                let description = match top.is_empty() {
+                    true => "No tracked invites yet".to_string(),
+                    false => top
+                        .iter()
+                        .enumerate()
+                        .map(|(i, (id, count))| {
+                            format!("**{}.** <@{}> - `{}` joins", i + 1, id.get(), count)
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                };
+
+                EmbedBuilder::new()
+                    .title("Top Inviters")
+                    .description(description)
+            }
+        };
+
+        let embed = embed
+            .color(0xFF8C00)
+            .footer(format!("{SERVICE_NAME} by {AUTHOR_COLON_THREE}"), None)
+            .build();
+
+        self.rest
+            .create_message_with_embed(ctx.channel_id, &[embed])
+            .await?;
+
+        Ok(())
+    }
 }
@@ -31,6 +31,7 @@ impl EventHandler {
 
             // Configuration commands
             "resetconfig" => self.resetconfig_command(config, ctx).await,
+            "config" => self.config_command(config, ctx, args).await,
             "reset" => self.reset_command(config, ctx, args).await,
             "setprefix" => self.setprefix_command(config, ctx, args).await,
             "setconfig" => self.setconfig_command(config, ctx, args).await,
@@ -38,6 +39,10 @@ impl EventHandler {
             "removealias" => self.remove_alias_command(config, ctx, args).await,
             "aliases" => self.list_aliases_command(config, ctx).await,
             "group" => self.group_command(config, ctx, args).await,
+            "logs" => self.logs_command(config, ctx, args).await,
+
+            // Automod commands
+            "automod" => self.automod_command(config, ctx, args).await,
 
             // Moderation commands
             "kick" => self.kick_command(config, ctx, args).await,
@@ -45,10 +50,15 @@ impl EventHandler {
             "unban" => self.unban_command(config, ctx, args).await,
             "mute" => self.mute_command(config, ctx, args).await,
             "unmute" => self.unmute_command(config, ctx, args).await,
+            "quarantine" => self.quarantine_command(config, ctx, args).await,
+            "unquarantine" => self.unquarantine_command(config, ctx, args).await,
             "warn" => self.warn_command(config, ctx, args).await,
             "pardon" => self.pardon_command(config, ctx, args).await,
+            "dehoist" => self.dehoist_command(config, ctx).await,
+            "raidmode" => self.raidmode_command(config, ctx, args).await,
 
             "lookup" => self.lookup_user_command(config, ctx, args).await,
+            "invites" => self.invites_command(config, ctx, args).await,
 
             // Music commands
             "enqueue" => self.enqueue_command(config, ctx, args).await,
@@ -8,6 +8,15 @@ pub const REMOVE_ALIAS: &str = "<alias:command>";
 
 pub const SET_CONFIG: &str = "<key:text> <value:text>";
 
+pub const LOGS_EVENT: &str = "<event:text>";
+pub const LOGS_TOGGLE: &str = "<event:text> [channel:channel_id]";
+pub const LOGS_CHANNEL: &str = "<event:text> <channel:channel_id>";
+pub const LOGS_ROUTE: &str = "<event:text> <channel:channel_id[]>";
+pub const LOGS_IGNORE: &str = "<kind:text> <target:id>";
+pub const LOGS_TEMPLATE: &str = "<event:text> <field:text> <value:text>";
+
+pub const AUTOMOD_TEST: &str = "[channel:channel_id] [member:user] <text:text>";
+
 pub const AUDIO_PLAYER_ID: &str = "<player_id:channel_id>";
 pub const AUDIO_ENQUEUE: &str = "<url:text> [player_id:channel_id]";
 pub const AUDIO_PLAYLIST: &str = "<name:text> [player_id:channel_id]";
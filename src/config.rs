@@ -1,4 +1,4 @@
-use std::env;
+use std::{env, path::PathBuf};
 
 #[derive(Debug, Clone)]
 pub struct Config {
@@ -11,6 +11,11 @@ pub struct Config {
     pub otlp_endpoint: String,
     pub otlp_auth: Option<String>,
     pub otlp_organization: Option<String>,
+    pub phishing_domains_path: Option<PathBuf>,
+    pub export_file_path: Option<PathBuf>,
+    pub export_file_max_bytes: u64,
+    pub export_http_url: Option<String>,
+    pub export_http_secret: Option<String>,
     pub shard_id: u32,
     pub num_shards: u32,
 }
@@ -30,6 +35,14 @@ impl Config {
                 .unwrap_or_else(|_| "http://localhost:5081".into()),
             otlp_auth: env::var("OTLP_AUTH").ok(),
             otlp_organization: env::var("OTLP_ORGANIZATION").ok(),
+            phishing_domains_path: env::var("PHISHING_DOMAINS_PATH").ok().map(PathBuf::from),
+            export_file_path: env::var("EXPORT_FILE_PATH").ok().map(PathBuf::from),
+            export_file_max_bytes: env::var("EXPORT_FILE_MAX_BYTES")
+                .unwrap_or_else(|_| (100 * 1024 * 1024).to_string())
+                .parse()
+                .map_err(|_| "EXPORT_FILE_MAX_BYTES is not a valid number")?,
+            export_http_url: env::var("EXPORT_HTTP_URL").ok(),
+            export_http_secret: env::var("EXPORT_HTTP_SECRET").ok(),
             shard_id: env::var("SHARD_ID")
                 .unwrap_or_else(|_| "0".into())
                 .parse()
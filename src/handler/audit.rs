@@ -0,0 +1,288 @@
+use std::collections::BTreeMap;
+
+use bm_lib::{
+    discord::{DiscordResult, Id},
+    model::{logging::LogEvent, Config, ConfigChange, ConfigHistoryEntry},
+};
+use serde_json::Value;
+use tracing::instrument;
+
+use super::EventHandler;
+
+/// Longest value shown in a diff, longer ones are cut.
+const MAX_VALUE_LEN: usize = 100;
+
+/// Fields whose values must never be shown, only that they changed. Names are
+/// as `Config` serializes them.
+const REDACTED_FIELDS: &[&str] = &["log_webhook_url"];
+
+/// One change as shown in the log and `config history`.
+pub(crate) fn format_change(change: &ConfigChange) -> String {
+    format!(
+        "`{}`: `{}` -> `{}`",
+        change.field, change.before, change.after
+    )
+}
+
+/// Flatten nested objects into dotted paths. Arrays are compared whole, see
+/// [`summarize_arrays`].
+fn flatten(prefix: &str, value: Value, out: &mut BTreeMap<String, Value>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                let path = match prefix.is_empty() {
+                    true => key,
+                    false => format!("{prefix}.{key}"),
+                };
+                flatten(&path, value, out);
+            }
+        }
+        value => {
+            out.insert(prefix.to_string(), value);
+        }
+    }
+}
+
+fn is_redacted(field: &str) -> bool {
+    REDACTED_FIELDS
+        .iter()
+        .any(|redacted| field == *redacted || field.starts_with(&format!("{redacted}.")))
+}
+
+fn redacted_value(value: Option<&Value>) -> String {
+    match value {
+        Some(Value::Null) | None => "unset".to_string(),
+        Some(_) => "<redacted>".to_string(),
+    }
+}
+
+fn display_value(value: Option<&Value>) -> String {
+    let rendered = match value {
+        Some(Value::Null) | None => "unset".to_string(),
+        Some(Value::String(s)) => s.clone(),
+        Some(value) => value.to_string(),
+    };
+
+    match rendered.char_indices().nth(MAX_VALUE_LEN) {
+        Some((end, _)) => format!("{}...", &rendered[..end]),
+        None => rendered,
+    }
+}
+
+fn array_items(value: Option<&Value>) -> Option<&[Value]> {
+    match value {
+        Some(Value::Array(items)) => Some(items.as_slice()),
+        _ => None,
+    }
+}
+
+/// Items of `items` with no equal item left in `other`, counting duplicates.
+fn count_missing(items: &[Value], other: &[Value]) -> usize {
+    let mut remaining: Vec<&Value> = other.iter().collect();
+    items
+        .iter()
+        .filter(|item| match remaining.iter().position(|r| r == item) {
+            Some(i) => {
+                remaining.swap_remove(i);
+                false
+            }
+            None => true,
+        })
+        .count()
+}
+
+/// Arrays are shown as entry counts and how many were added and removed.
+/// They hold censor filter lists, which shouldn't be repeated in logs.
+fn summarize_arrays(before: Option<&Value>, after: Option<&Value>) -> Option<(String, String)> {
+    let (old, new) = match (array_items(before), array_items(after)) {
+        (None, None) => return None,
+        (old, new) => (old.unwrap_or_default(), new.unwrap_or_default()),
+    };
+
+    let count = |value: Option<&Value>, items: &[Value]| match value {
+        Some(Value::Null) | None => "unset".to_string(),
+        _ => match items.len() {
+            1 => "1 entry".to_string(),
+            len => format!("{len} entries"),
+        },
+    };
+
+    let after_summary = match (count_missing(new, old), count_missing(old, new)) {
+        // Same entries, either reordered or an empty list set or unset
+        (0, 0) if old.is_empty() => count(after, new),
+        (0, 0) => format!("{} (reordered)", count(after, new)),
+        (added, removed) => format!("{} ({added} added, {removed} removed)", count(after, new)),
+    };
+
+    Some((count(before, old), after_summary))
+}
+
+/// Before and after values of a changed field as shown in the log.
+fn display_change(field: &str, before: Option<&Value>, after: Option<&Value>) -> (String, String) {
+    if is_redacted(field) {
+        return (redacted_value(before), redacted_value(after));
+    }
+
+    summarize_arrays(before, after).unwrap_or_else(|| (display_value(before), display_value(after)))
+}
+
+/// Field-level differences between two configs.
+fn diff_configs(before: &Config, after: &Config) -> Vec<ConfigChange> {
+    let (Ok(before), Ok(after)) = (serde_json::to_value(before), serde_json::to_value(after))
+    else {
+        return Vec::new();
+    };
+
+    let mut old = BTreeMap::new();
+    let mut new = BTreeMap::new();
+    flatten("", before, &mut old);
+    flatten("", after, &mut new);
+
+    let mut fields: Vec<&String> = old.keys().chain(new.keys()).collect();
+    fields.sort();
+    fields.dedup();
+
+    fields
+        .into_iter()
+        .filter(|field| old.get(*field) != new.get(*field))
+        .map(|field| {
+            let (before, after) = display_change(field, old.get(field), new.get(field));
+            ConfigChange {
+                field: field.clone(),
+                before,
+                after,
+            }
+        })
+        .collect()
+}
+
+impl EventHandler {
+    /// Store and log what changed between two versions of a guild's config.
+    /// Failures are logged and never fail the config update itself.
+    #[instrument(skip(self, before, after))]
+    pub(super) async fn record_config_change(
+        &self,
+        guild_id: &Id,
+        actor_id: &Id,
+        before: &Config,
+        after: &Config,
+    ) {
+        let changes = diff_configs(before, after);
+        if changes.is_empty() {
+            return;
+        }
+
+        let entry = ConfigHistoryEntry {
+            guild_id: *guild_id,
+            timestamp: chrono::Utc::now().timestamp(),
+            actor_id: *actor_id,
+            changes,
+        };
+
+        if let Err(e) = self.db.insert_config_history(&entry).await {
+            tracing::warn!(error = ?e, "Failed to store config history");
+        }
+
+        let _ = self
+            .log_event(LogEvent::ConfigUpdate {
+                guild_id: *guild_id,
+                user_id: *actor_id,
+                count: entry.changes.len() as u64,
+                changes: entry
+                    .changes
+                    .iter()
+                    .map(format_change)
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            })
+            .await;
+    }
+
+    /// Recent config changes for the guild, newest first.
+    #[instrument(skip(self))]
+    pub async fn get_config_history(
+        &self,
+        guild_id: &Id,
+        limit: usize,
+    ) -> DiscordResult<Vec<ConfigHistoryEntry>> {
+        let history = self.db.get_config_history(guild_id, limit as i64).await?;
+
+        Ok(history)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WEBHOOK_TOKEN: &str = "s3cr3t-webhook-token";
+
+    fn webhook_url(id: u64) -> String {
+        format!("https://discord.com/api/webhooks/{id}/{WEBHOOK_TOKEN}")
+    }
+
+    #[test]
+    fn redacted_fields_exist_in_serialized_config() {
+        let mut config = Config::new(&Id::new(1));
+        config.log_webhook_url = Some(webhook_url(1));
+
+        let value = serde_json::to_value(&config).unwrap();
+        for field in REDACTED_FIELDS {
+            assert!(
+                value.get(field).is_some(),
+                "`{field}` is not a Config field"
+            );
+        }
+    }
+
+    #[test]
+    fn webhook_token_never_in_diff() {
+        let unset = Config::new(&Id::new(1));
+        let mut first = unset.clone();
+        first.log_webhook_url = Some(webhook_url(1));
+        let mut second = unset.clone();
+        second.log_webhook_url = Some(webhook_url(2));
+
+        for (before, after) in [(&unset, &first), (&first, &second), (&second, &unset)] {
+            let changes = diff_configs(before, after);
+            assert!(changes.iter().any(|c| c.field == "log_webhook_url"));
+
+            for change in &changes {
+                assert!(!format_change(change).contains(WEBHOOK_TOKEN));
+                assert!(!format_change(change).contains("webhooks"));
+            }
+        }
+    }
+
+    #[test]
+    fn arrays_are_summarized() {
+        let words = |words: &[&str]| Some(serde_json::json!(words));
+
+        assert_eq!(
+            display_change(
+                "automod.global.censors.Word.filters",
+                words(&["badword", "other"]).as_ref(),
+                words(&["other", "slur", "slur"]).as_ref(),
+            ),
+            (
+                "2 entries".to_string(),
+                "3 entries (2 added, 1 removed)".to_string()
+            )
+        );
+        assert_eq!(
+            display_change("list", None, words(&["badword"]).as_ref()),
+            (
+                "unset".to_string(),
+                "1 entry (1 added, 0 removed)".to_string()
+            )
+        );
+        assert_eq!(
+            display_change(
+                "list",
+                words(&["a", "b"]).as_ref(),
+                words(&["b", "a"]).as_ref()
+            ),
+            ("2 entries".to_string(), "2 entries (reordered)".to_string())
+        );
+    }
+}
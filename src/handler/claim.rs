@@ -0,0 +1,90 @@
+use std::time::Duration;
+
+use bm_lib::discord::DiscordResult;
+use tracing::instrument;
+
+use super::EventHandler;
+
+/// Take a claim if nobody holds it.
+///
+/// `KEYS[1]` is the claim, `ARGV[1]` its TTL in ms.
+const CLAIM_SCRIPT: &str = r#"
+if redis.call('SET', KEYS[1], '1', 'NX', 'PX', ARGV[1]) then
+    return 1
+end
+return 0
+"#;
+
+/// Release a claim taken with [`CLAIM_SCRIPT`].
+///
+/// `KEYS[1]` is the claim.
+const RELEASE_SCRIPT: &str = r#"
+return redis.call('DEL', KEYS[1])
+"#;
+
+/// A held claim, released on [`ClaimGuard::release`] or when dropped.
+pub struct ClaimGuard {
+    handler: EventHandler,
+    key: Option<String>,
+}
+
+impl ClaimGuard {
+    pub async fn release(mut self) {
+        if let Some(key) = self.key.take() {
+            self.handler.release_claim(&key).await;
+        }
+    }
+}
+
+impl Drop for ClaimGuard {
+    fn drop(&mut self) {
+        // Only reached without `release`, e.g. while unwinding from a panic
+        if let Some(key) = self.key.take() {
+            let handler = self.handler.clone();
+            tokio::spawn(async move {
+                handler.release_claim(&key).await;
+            });
+        }
+    }
+}
+
+impl EventHandler {
+    /// Claim `key` for at most `ttl` so only one shard does the work behind
+    /// it. Returns `false` when the claim is already held.
+    #[instrument(skip(self))]
+    pub async fn claim(&self, key: &str, ttl: Duration) -> DiscordResult<bool> {
+        let claimed = self
+            .cache
+            .eval::<u64>(CLAIM_SCRIPT, &[key], &[ttl.as_millis().to_string()])
+            .await?;
+
+        Ok(claimed == 1)
+    }
+
+    /// [`Self::claim`], returning a guard that releases the claim when it is
+    /// dropped. Meant for claims held by a spawned task, so a panic in the
+    /// task doesn't keep the claim until its TTL.
+    pub async fn claim_guarded(
+        &self,
+        key: String,
+        ttl: Duration,
+    ) -> DiscordResult<Option<ClaimGuard>> {
+        if !self.claim(&key, ttl).await? {
+            return Ok(None);
+        }
+
+        Ok(Some(ClaimGuard {
+            handler: self.clone(),
+            key: Some(key),
+        }))
+    }
+
+    /// Release a claim taken with [`Self::claim`]. A failed release is only
+    /// logged, the claim still expires with its TTL.
+    #[instrument(skip(self))]
+    pub async fn release_claim(&self, key: &str) {
+        if let Err(e) = self.cache.eval::<u64>(RELEASE_SCRIPT, &[key], &[]).await {
+            tracing::warn!(key, error = ?e, "Failed to release claim");
+        }
+    }
+}
@@ -11,6 +11,9 @@ use super::EventHandler;
 const CONFIG_TTL: Duration = Duration::from_secs(60);
 const USER_TTL: Duration = Duration::from_secs(180);
 const MEMBER_TTL: Duration = Duration::from_secs(600);
+const INVITE_GUILD_TTL: Duration = Duration::from_secs(3600);
+const INVITE_GUILD_FAILURE_TTL: Duration = Duration::from_secs(300);
+const THREAD_PARENT_TTL: Duration = Duration::from_secs(3600);
 const API_TIMEOUT: Duration = Duration::from_secs(30);
 
 const GUILD_COUNT_KEY: &str = "guild_count";
@@ -36,7 +39,7 @@ fn user_cache_key(user_id: &Id) -> String {
 }
 
 #[inline]
-fn roles_cache_key(guild_id: &Id, user_id: &Id) -> String {
+pub(crate) fn roles_cache_key(guild_id: &Id, user_id: &Id) -> String {
     format!("roles:{}:{}", guild_id, user_id)
 }
 
@@ -55,6 +58,16 @@ fn automod_cache_key(guild_id: &Id) -> String {
     format!("automod:{}", guild_id)
 }
 
+#[inline]
+fn invite_guild_cache_key(code: &str) -> String {
+    format!("invite_guild:{}", code)
+}
+
+#[inline]
+fn thread_parent_cache_key(channel_id: &Id) -> String {
+    format!("thread_parent:{}", channel_id)
+}
+
 #[inline]
 fn voice_state_cache_key(guild_id: &Id, user_id: &Id) -> String {
     format!("voice_state:{}:{}", guild_id, user_id)
@@ -95,11 +108,9 @@ impl EventHandler {
     }
 
     #[instrument(skip(self))]
-    pub async fn reset_config(&self, guild_id: &Id) -> DiscordResult<()> {
+    pub async fn reset_config(&self, guild_id: &Id, actor_id: &Id) -> DiscordResult<()> {
         let config = Config::new(guild_id);
-        self.cache.set(guild_id, &config, Some(CONFIG_TTL)).await?;
-        self.db.update_config(guild_id, &config).await?;
-        Ok(())
+        self.set_config(guild_id, &config, actor_id).await
     }
 
     #[tracing::instrument(skip(self))]
@@ -118,10 +129,31 @@ impl EventHandler {
         Ok(config)
     }
 
+    /// Store a guild's config, recording who changed what.
     #[instrument(skip(self, config))]
-    pub async fn set_config(&self, guild_id: &Id, config: &Config) -> DiscordResult<()> {
+    pub async fn set_config(
+        &self,
+        guild_id: &Id,
+        config: &Config,
+        actor_id: &Id,
+    ) -> DiscordResult<()> {
+        // The audit entry is best effort, a failed read must not block the save
+        let before = match self.get_config(guild_id).await {
+            Ok(before) => Some(before),
+            Err(e) => {
+                tracing::warn!(error = ?e, "Failed to read config before update, skipping audit entry");
+                None
+            }
+        };
+
         self.cache.set(guild_id, config, Some(CONFIG_TTL)).await?;
         self.db.update_config(guild_id, config).await?;
+
+        if let Some(before) = before {
+            self.record_config_change(guild_id, actor_id, &before, config)
+                .await;
+        }
+
         Ok(())
     }
 
@@ -301,6 +333,67 @@ impl EventHandler {
         Ok(channel)
     }
 
+    /// Resolve the guild an invite code points to.
+    #[instrument(skip(self))]
+    pub async fn get_invite_guild(&self, code: &str) -> DiscordResult<Option<Id>> {
+        let key = invite_guild_cache_key(code);
+        if let Some(guild_id) = self.cache.get::<String, Option<Id>>(&key).await? {
+            return Ok(guild_id);
+        }
+
+        // Unknown or expired codes are remembered briefly too, so a bad
+        // invite posted repeatedly doesn't cost a request every time. Other
+        // failures may be transient and are never cached, or the guild's own
+        // invites would look foreign until the entry expired.
+        let guild_id = match self.api_with_timeout(self.rest.get_invite(code)).await {
+            Ok(invite) => invite.guild.map(|guild| guild.id),
+            Err(DiscordError::NotFound) => None,
+            Err(e) => return Err(e),
+        };
+
+        let ttl = match guild_id {
+            Some(_) => INVITE_GUILD_TTL,
+            None => INVITE_GUILD_FAILURE_TTL,
+        };
+        self.cache.set(&key, &guild_id, Some(ttl)).await?;
+
+        Ok(guild_id)
+    }
+
+    /// Parent of a channel: the category for regular channels, the channel a
+    /// thread was created in for threads.
+    ///
+    /// Threads are not part of the guild channel list, so they are fetched
+    /// individually and only their parent is cached.
+    #[instrument(skip(self))]
+    pub async fn get_channel_parent(
+        &self,
+        guild_id: &Id,
+        channel_id: &Id,
+    ) -> DiscordResult<Option<Id>> {
+        let channels = self.get_channels(guild_id).await?;
+        if let Some(channel) = channels.iter().find(|c| c.id == *channel_id) {
+            return Ok(channel.parent_id);
+        }
+
+        let key = thread_parent_cache_key(channel_id);
+        if let Some(parent_id) = self.cache.get::<String, Id>(&key).await? {
+            return Ok(Some(parent_id));
+        }
+
+        let channel = self
+            .api_with_timeout(self.rest.get_channel(channel_id))
+            .await?;
+
+        if let Some(parent_id) = &channel.parent_id {
+            self.cache
+                .set(&key, parent_id, Some(THREAD_PARENT_TTL))
+                .await?;
+        }
+
+        Ok(channel.parent_id)
+    }
+
     #[instrument(skip(self))]
     pub async fn get_voice_state_channel(
         &self,
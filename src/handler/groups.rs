@@ -6,12 +6,14 @@ use bm_lib::{
 use tracing::instrument;
 
 use super::EventHandler;
+
 impl EventHandler {
     #[instrument(skip(self))]
     pub async fn new_group<'a>(
         &self,
         config: &'a mut Config,
         guild_id: &Id,
+        actor_id: &Id,
         name: &str,
     ) -> DiscordResult<&'a Config> {
         let group = PermissionGroup::new(name);
@@ -22,7 +24,7 @@ impl EventHandler {
             config.permission_groups = Some(vec![group]);
         }
 
-        self.set_config(guild_id, &config).await?;
+        self.set_config(guild_id, &config, actor_id).await?;
 
         Ok(config)
     }
@@ -32,13 +34,14 @@ impl EventHandler {
         &self,
         config: &'a mut Config,
         guild_id: &Id,
+        actor_id: &Id,
         name: &str,
     ) -> DiscordResult<&'a Config> {
         if let Some(groups) = config.permission_groups.as_mut() {
             groups.retain(|group| group.name != name);
         }
 
-        self.set_config(guild_id, &config).await?;
+        self.set_config(guild_id, &config, actor_id).await?;
 
         Ok(config)
     }
@@ -48,6 +51,7 @@ impl EventHandler {
         &self,
         config: &'a mut Config,
         guild_id: &Id,
+        actor_id: &Id,
         group_name: &str,
         permissions: Vec<Permission>,
     ) -> DiscordResult<&'a Config> {
@@ -59,7 +63,7 @@ impl EventHandler {
             }
         }
 
-        self.set_config(guild_id, &config).await?;
+        self.set_config(guild_id, &config, actor_id).await?;
 
         Ok(config)
     }
@@ -69,6 +73,7 @@ impl EventHandler {
         &self,
         config: &'a mut Config,
         guild_id: &Id,
+        actor_id: &Id,
         group_name: &str,
         permissions: Vec<Permission>,
     ) -> DiscordResult<&'a Config> {
@@ -80,7 +85,7 @@ impl EventHandler {
             }
         }
 
-        self.set_config(guild_id, &config).await?;
+        self.set_config(guild_id, &config, actor_id).await?;
 
         Ok(config)
     }
@@ -90,6 +95,7 @@ impl EventHandler {
         &self,
         config: &'a mut Config,
         guild_id: &Id,
+        actor_id: &Id,
         group_name: &str,
         user_id: &Id,
     ) -> DiscordResult<&'a Config> {
@@ -99,7 +105,7 @@ impl EventHandler {
             }
         }
 
-        self.set_config(guild_id, &config).await?;
+        self.set_config(guild_id, &config, actor_id).await?;
 
         Ok(config)
     }
@@ -109,6 +115,7 @@ impl EventHandler {
         &self,
         config: &'a mut Config,
         guild_id: &Id,
+        actor_id: &Id,
         group_name: &str,
         user_id: &Id,
     ) -> DiscordResult<&'a Config> {
@@ -118,7 +125,7 @@ impl EventHandler {
             }
         }
 
-        self.set_config(guild_id, &config).await?;
+        self.set_config(guild_id, &config, actor_id).await?;
 
         Ok(config)
     }
@@ -143,6 +150,7 @@ impl EventHandler {
         &self,
         config: &'a mut Config,
         guild_id: &Id,
+        actor_id: &Id,
         group_name: &str,
         role_id: &Id,
     ) -> DiscordResult<&'a Config> {
@@ -152,7 +160,7 @@ impl EventHandler {
             }
         }
 
-        self.set_config(guild_id, &config).await?;
+        self.set_config(guild_id, &config, actor_id).await?;
 
         Ok(config)
     }
@@ -162,6 +170,7 @@ impl EventHandler {
         &self,
         config: &'a mut Config,
         guild_id: &Id,
+        actor_id: &Id,
         group_name: &str,
         role_id: &Id,
     ) -> DiscordResult<&'a Config> {
@@ -171,7 +180,7 @@ impl EventHandler {
             }
         }
 
-        self.set_config(guild_id, &config).await?;
+        self.set_config(guild_id, &config, actor_id).await?;
 
         Ok(config)
     }
@@ -191,22 +200,98 @@ impl EventHandler {
         Ok(None)
     }
 
-    #[instrument(skip(self))]
+    #[instrument(skip(self, config))]
     pub async fn get_user_groups<'a>(
         &self,
         config: &'a Config,
         user_id: &Id,
+        member_roles: &[Id],
     ) -> Vec<&'a PermissionGroup> {
-        let mut user_groups = Vec::new();
+        member_groups(config, user_id, member_roles)
+    }
+}
 
-        if let Some(groups) = &config.permission_groups {
-            for group in groups {
-                if group.users.contains(user_id) {
-                    user_groups.push(group);
-                }
-            }
-        }
+/// Permission groups a member belongs to, either directly by user ID or via
+/// one of their Discord roles.
+///
+/// This is the only place group membership is decided; permission checks and
+/// automod bypass both go through it.
+pub fn member_groups<'a>(
+    config: &'a Config,
+    user_id: &Id,
+    member_roles: &[Id],
+) -> Vec<&'a PermissionGroup> {
+    let Some(groups) = &config.permission_groups else {
+        return Vec::new();
+    };
+
+    groups
+        .iter()
+        .filter(|group| {
+            group.users.contains(user_id)
+                || group.roles.iter().any(|role| member_roles.contains(role))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GUILD: u64 = 1;
+    const USER: u64 = 10;
+    const ROLE: u64 = 20;
+
+    fn group(name: &str, users: &[u64], roles: &[u64]) -> PermissionGroup {
+        let mut group = PermissionGroup::new(name);
+        group.users = users.iter().copied().map(Id::new).collect();
+        group.roles = roles.iter().copied().map(Id::new).collect();
+        group
+    }
+
+    fn config(groups: Vec<PermissionGroup>) -> Config {
+        let mut config = Config::new(&Id::new(GUILD));
+        config.permission_groups = Some(groups);
+        config
+    }
+
+    fn names(groups: Vec<&PermissionGroup>) -> Vec<&str> {
+        groups.iter().map(|group| group.name.as_str()).collect()
+    }
+
+    #[test]
+    fn matches_by_user() {
+        let config = config(vec![group("users", &[USER], &[])]);
+        let groups = member_groups(&config, &Id::new(USER), &[]);
+        assert_eq!(names(groups), ["users"]);
+    }
+
+    #[test]
+    fn matches_by_role() {
+        let config = config(vec![group("roles", &[], &[ROLE])]);
+        let groups = member_groups(&config, &Id::new(USER), &[Id::new(ROLE)]);
+        assert_eq!(names(groups), ["roles"]);
+    }
+
+    #[test]
+    fn matches_user_and_role_groups_once_each() {
+        let config = config(vec![
+            group("users", &[USER], &[]),
+            group("both", &[USER], &[ROLE]),
+            group("roles", &[], &[ROLE]),
+            group("other", &[USER + 1], &[ROLE + 1]),
+        ]);
+        let groups = member_groups(&config, &Id::new(USER), &[Id::new(ROLE)]);
+        assert_eq!(names(groups), ["users", "both", "roles"]);
+    }
+
+    #[test]
+    fn no_membership() {
+        let config = config(vec![group("other", &[USER + 1], &[ROLE + 1])]);
+        assert!(member_groups(&config, &Id::new(USER), &[Id::new(ROLE)]).is_empty());
 
-        user_groups
+        let mut config = Config::new(&Id::new(GUILD));
+        config.permission_groups = None;
+        assert!(member_groups(&config, &Id::new(USER), &[Id::new(ROLE)]).is_empty());
     }
 }
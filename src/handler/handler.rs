@@ -6,12 +6,14 @@ use bm_lib::discord::{
     commands::{Args, Ctx},
     Channel, DiscordError, DiscordResult, DiscordWebsocket, Event, Guild, GuildBanEvent,
     GuildMember, GuildMemberRemove, GuildRoleDeleteEvent, GuildRoleEvent, Id, InviteCreateEvent,
-    InviteDeleteEvent, Message, MessageDelete, Ready, ResumeState, ShardConfig,
+    InviteDeleteEvent, Message, MessageDelete, MessageDeleteBulk, Ready, ResumeState, ShardConfig,
 };
-use bm_lib::model::logging::LogEvent;
+use bm_lib::model::{logging::LogEvent, Config};
+use bm_lib::permissions::Permission;
 use tracing::instrument;
 
-use super::EventHandler;
+use super::{invites::UsedInvite, EventHandler};
+use crate::logging::{render_transcript, LogAttachment};
 
 /// Calculates exponential backoff delay with a maximum cap.
 fn reconnect_delay(base: Duration, max: Duration, attempts: u32) -> Duration {
@@ -94,6 +96,10 @@ impl EventHandler {
                 parent: None, "MessageDelete",
                 message_id = %d.id, channel_id = %d.channel_id
             ),
+            Event::MessageDeleteBulk(d) => tracing::info_span!(
+                parent: None, "MessageDeleteBulk",
+                channel_id = %d.channel_id, count = d.ids.len()
+            ),
             Event::ChannelCreate(ch) => tracing::info_span!(
                 parent: None, "ChannelCreate",
                 channel_id = %ch.id, guild_id = ?ch.guild_id
@@ -149,21 +155,33 @@ impl EventHandler {
                 Event::GuildUpdate(guild) => self.on_guild_update(guild).await?,
                 Event::GuildMemberAdd(member) => {
                     self.on_member_update(member).await?;
+                    // Working out the invite takes a REST call, so join-time
+                    // automod runs alongside it instead of waiting
+                    let ((), invite) =
+                        tokio::join!(self.on_member_join(member), self.member_invite(member));
                     // Log member add event
                     let _ = self
                         .log_event(LogEvent::GuildMemberAdd {
                             guild_id: member.guild_id,
                             user_id: member.user.id,
                             username: member.user.username.to_string(),
+                            invite_code: invite.as_ref().map(|i| i.code.clone()),
+                            inviter_id: invite.and_then(|i| i.inviter_id),
                         })
                         .await;
                 }
-                Event::GuildMemberUpdate(member) => self.on_member_update(member).await?,
+                Event::GuildMemberUpdate(member) => {
+                    self.on_member_update(member).await?;
+                    if let Ok(config) = self.get_config(&member.guild_id).await {
+                        self.check_member_name(&config, member).await;
+                    }
+                }
                 Event::GuildMemberRemove(member) => self.on_member_remove(member).await?,
                 Event::VoiceStateUpdate(vs) => self.on_voice_state_update(vs).await?,
                 Event::VoiceServerUpdate(vs) => self.on_voice_server_update(vs).await?,
-                Event::MessageUpdate(_) => {} // Not handled yet
+                Event::MessageUpdate(message) => self.on_message_update(message).await?,
                 Event::MessageDelete(d) => self.on_message_delete(d).await?,
+                Event::MessageDeleteBulk(d) => self.on_message_delete_bulk(d).await?,
                 Event::ChannelCreate(ch) => self.on_channel_create(ch).await?,
                 Event::ChannelUpdate(ch) => self.on_channel_update(ch).await?,
                 Event::ChannelDelete(ch) => self.on_channel_delete(ch).await?,
@@ -463,9 +481,19 @@ impl EventHandler {
         }
         self.set_guild(guild).await?;
 
+        // Drop voice activity for members who left while we were disconnected
+        if let Err(e) = self.seed_voice_activity(guild).await {
+            tracing::warn!(error = ?e, "Failed to seed voice activity");
+        }
+
         // Populate channels cache
         self.get_channels(&guild.id).await?;
 
+        // Needs Manage Guild, without it joins just aren't credited
+        if let Err(e) = self.seed_invites(&guild.id).await {
+            tracing::debug!(error = ?e, "Failed to seed invites");
+        }
+
         self.increment_guild_count().await
     }
 
@@ -512,6 +540,76 @@ impl EventHandler {
         Ok(())
     }
 
+    /// Join-time automod: raid detection and the account age gate, then the
+    /// name filter for members that were not removed. Failures are logged and
+    /// never stop the rest of the event handling.
+    async fn on_member_join(&self, member: &GuildMember) {
+        let config = match self.get_config(&member.guild_id).await {
+            Ok(config) => config,
+            Err(e) => {
+                tracing::warn!(error = ?e, "Failed to load config for member join");
+                return;
+            }
+        };
+
+        if !member.user.bot {
+            match self
+                .handle_raid_join(&config, &member.guild_id, &member.user.id)
+                .await
+            {
+                Ok(true) => return,
+                Ok(false) => {}
+                Err(e) => tracing::warn!(error = ?e, "Raid detection failed"),
+            }
+
+            match self
+                .handle_account_age(&config, &member.guild_id, &member.user)
+                .await
+            {
+                Ok(true) => return,
+                Ok(false) => {}
+                Err(e) => tracing::warn!(error = ?e, "Account age check failed"),
+            }
+        }
+
+        self.check_member_name(&config, member).await;
+    }
+
+    /// The invite a joining member used, if it can be worked out. Bots are
+    /// added through OAuth and never use an invite.
+    async fn member_invite(&self, member: &GuildMember) -> Option<UsedInvite> {
+        if member.user.bot {
+            return None;
+        }
+
+        match self
+            .resolve_member_invite(&member.guild_id, &member.user.id)
+            .await
+        {
+            Ok(invite) => invite,
+            Err(e) => {
+                tracing::warn!(error = ?e, "Failed to resolve member invite");
+                None
+            }
+        }
+    }
+
+    /// Run the automod name filter for a joining or updated member.
+    async fn check_member_name(&self, config: &Config, member: &GuildMember) {
+        if let Err(e) = self
+            .handle_member_name(
+                config,
+                &member.guild_id,
+                &member.user,
+                member.nick.as_deref(),
+                &member.roles,
+            )
+            .await
+        {
+            tracing::warn!(error = ?e, "Name filter failed");
+        }
+    }
+
     #[tracing::instrument(skip(self, member_remove), fields(guild_id = %member_remove.guild_id, user_id = %member_remove.user.id))]
     async fn on_member_remove(&self, member_remove: &GuildMemberRemove) -> DiscordResult<()> {
         // Remove this guild from the user's member guilds cache
@@ -604,23 +702,163 @@ impl EventHandler {
         Ok(())
     }
 
+    #[tracing::instrument(
+        skip(self, message),
+        fields(
+            message_id = %message.id,
+            channel_id = %message.channel_id,
+            guild_id = ?message.guild_id
+        )
+    )]
+    async fn on_message_update(&self, message: &Message) -> DiscordResult<()> {
+        let Some(guild_id) = message.guild_id else {
+            return Ok(()); // Ignore DM messages
+        };
+
+        // Embed unfurls and similar partial updates carry no author or content
+        let Some(author) = message.author.as_ref() else {
+            return Ok(());
+        };
+
+        // Attachments can outlive the text, so only skip when both are gone
+        if author.bot || (message.content.is_empty() && message.attachments.is_empty()) {
+            return Ok(());
+        }
+
+        // Embed unfurls and pins arrive as updates without an edit time
+        if message.edited_timestamp.is_none() {
+            return Ok(());
+        }
+
+        let config = self.get_config(&guild_id).await?;
+
+        let mut unchanged = false;
+
+        if self
+            .message_cache_enabled(&config, &guild_id, &message.channel_id)
+            .await
+        {
+            match self.update_cached_message(message).await {
+                Ok(before) => {
+                    let before = before.map(|cached| cached.content);
+                    // Unfurls on an already edited message re-send its content
+                    unchanged = before.as_deref() == Some(message.content.as_str());
+                    if !unchanged {
+                        let _ = self
+                            .log_event(LogEvent::MessageEdit {
+                                guild_id,
+                                channel_id: message.channel_id,
+                                message_id: message.id,
+                                user_id: author.id,
+                                username: author.username.to_string(),
+                                before,
+                                after: message.content.clone(),
+                            })
+                            .await;
+                    }
+                }
+                Err(e) => tracing::warn!(error = ?e, "Failed to update cached message"),
+            }
+        }
+
+        // Content automod has already seen must not count against the member
+        // again
+        if unchanged || !config.automod_enabled {
+            return Ok(());
+        }
+
+        let roles = match self.get_member_roles(&guild_id, &author.id).await {
+            Ok(r) => r,
+            Err(e) => {
+                tracing::warn!("Failed to fetch member roles: {}", e);
+                return Ok(());
+            }
+        };
+
+        let Some(ctx) = Ctx::new(message, &roles) else {
+            tracing::error!("Failed to create context for message {}", message.id);
+            return Ok(());
+        };
+
+        if let Err(e) = self.handle_automod_edit(&config, &ctx).await {
+            tracing::error!("Automod processing failed for edit: {}", e);
+        }
+
+        Ok(())
+    }
+
     #[tracing::instrument(skip(self, message_delete), fields(message_id = %message_delete.id, channel_id = %message_delete.channel_id, guild_id = ?message_delete.guild_id))]
     async fn on_message_delete(&self, message_delete: &MessageDelete) -> DiscordResult<()> {
         let Some(guild_id) = message_delete.guild_id else {
             return Ok(()); // Ignore DM messages
         };
 
+        let cached = match self
+            .take_cached_message(&message_delete.channel_id, &message_delete.id)
+            .await
+        {
+            Ok(cached) => cached,
+            Err(e) => {
+                tracing::warn!(error = ?e, "Failed to read cached message");
+                None
+            }
+        };
+
         let _ = self
             .log_event(LogEvent::MessageDelete {
                 guild_id,
                 channel_id: message_delete.channel_id,
                 message_id: message_delete.id,
+                user_id: cached.as_ref().map(|m| m.author_id),
+                username: cached.as_ref().map(|m| m.author_name.clone()),
+                content: cached.as_ref().map(|m| m.content.clone()),
+                attachments: cached.map(|m| m.attachments).unwrap_or_default(),
             })
             .await;
 
         Ok(())
     }
 
+    /// Log a bulk delete as a single event with a transcript of whatever was
+    /// cached.
+    #[tracing::instrument(skip(self, bulk), fields(channel_id = %bulk.channel_id, guild_id = ?bulk.guild_id, count = bulk.ids.len()))]
+    async fn on_message_delete_bulk(&self, bulk: &MessageDeleteBulk) -> DiscordResult<()> {
+        let Some(guild_id) = bulk.guild_id else {
+            return Ok(());
+        };
+
+        let mut cached = match self.take_cached_messages(&bulk.channel_id, &bulk.ids).await {
+            Ok(cached) => cached,
+            Err(e) => {
+                tracing::warn!(error = ?e, "Failed to read cached messages");
+                Vec::new()
+            }
+        };
+
+        let event = LogEvent::MessageDeleteBulk {
+            guild_id,
+            channel_id: bulk.channel_id,
+            count: bulk.ids.len() as u64,
+            cached: cached.len() as u64,
+        };
+
+        let _ = if cached.is_empty() {
+            self.log_event(event).await
+        } else {
+            let transcript = render_transcript(&bulk.channel_id, &mut cached, bulk.ids.len());
+            self.log_event_with_attachment(
+                event,
+                LogAttachment {
+                    filename: format!("bulk-delete-{}.txt", bulk.channel_id),
+                    data: transcript.into_bytes(),
+                },
+            )
+            .await
+        };
+
+        Ok(())
+    }
+
     #[tracing::instrument(skip(self, role_event), fields(guild_id = %role_event.guild_id, role_id = %role_event.role.id))]
     async fn on_guild_role_create(&self, role_event: &GuildRoleEvent) -> DiscordResult<()> {
         let _ = self
@@ -691,6 +929,10 @@ impl EventHandler {
             return Ok(()); // Ignore DM invites
         };
 
+        if let Err(e) = self.track_invite_create(&guild_id, invite).await {
+            tracing::warn!(error = ?e, "Failed to track invite");
+        }
+
         let _ = self
             .log_event(LogEvent::InviteCreate {
                 guild_id,
@@ -709,6 +951,10 @@ impl EventHandler {
             return Ok(()); // Ignore DM invites
         };
 
+        if let Err(e) = self.track_invite_delete(&guild_id, &invite.code).await {
+            tracing::warn!(error = ?e, "Failed to track invite");
+        }
+
         let _ = self
             .log_event(LogEvent::InviteDelete {
                 guild_id,
@@ -720,6 +966,33 @@ impl EventHandler {
         Ok(())
     }
 
+    /// Whether the message is an `automod test` invocation by someone allowed to
+    /// run it. The text under test is expected to trip automod, so these
+    /// messages skip it and the command deletes them once it has replied.
+    async fn is_automod_test(
+        &self,
+        config: &Config,
+        ctx: &Ctx<'_>,
+        parsed: Option<(&str, &str)>,
+    ) -> bool {
+        let Some((command, args)) = parsed else {
+            return false;
+        };
+
+        if !command.eq_ignore_ascii_case("automod")
+            || !args
+                .split_whitespace()
+                .next()
+                .is_some_and(|sub| sub.eq_ignore_ascii_case("test"))
+        {
+            return false;
+        }
+
+        self.check_permission(config, ctx, Permission::CONFIG_EDIT)
+            .await
+            .unwrap_or(false)
+    }
+
     /// Processes a message, running automod checks and dispatching commands.
     #[instrument(skip(self, ctx), fields(guild_id = %ctx.guild_id, user_id = %ctx.user.id, message_id = %ctx.message.id, channel_id = %ctx.channel_id))]
     pub async fn handle_message(&self, ctx: &Ctx<'_>) -> DiscordResult<()> {
@@ -732,16 +1005,25 @@ impl EventHandler {
             }
         };
 
-        if config.automod_enabled {
+        if self
+            .message_cache_enabled(&config, ctx.guild_id, ctx.channel_id)
+            .await
+        {
+            if let Err(e) = self.cache_message(ctx.guild_id, ctx.message).await {
+                tracing::warn!(error = ?e, "Failed to cache message");
+            }
+        }
+
+        let bot_mention = self.bot_mention.get().map(|s| s.as_str()).unwrap_or("");
+        let parsed = Self::parse_command(&ctx.message.content, &config.prefix, bot_mention);
+
+        if config.automod_enabled && !self.is_automod_test(&config, ctx, parsed).await {
             if let Err(e) = self.handle_automod(&config, ctx).await {
                 tracing::error!("Automod processing failed: {}", e);
             }
         }
 
-        let bot_mention = self.bot_mention.get().map(|s| s.as_str()).unwrap_or("");
-        let Some((command_str, args_str)) =
-            Self::parse_command(&ctx.message.content, &config.prefix, bot_mention)
-        else {
+        let Some((command_str, args_str)) = parsed else {
             return Ok(());
         };
 
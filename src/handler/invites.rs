@@ -0,0 +1,493 @@
+use std::collections::HashMap;
+
+use bm_lib::discord::{DiscordError, DiscordResult, Id, Invite, InviteCreateEvent};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use super::EventHandler;
+
+/// Members remembered per inviter for the `invites` command.
+const INVITE_JOINS_CAP: u64 = 100;
+
+/// Count a join for the inviter and remember who joined.
+///
+/// `KEYS[1]` is the inviter leaderboard, `KEYS[2]` the inviter's joins.
+/// `ARGV[1]` is the inviter, `ARGV[2]` the member, `ARGV[3]` the cap.
+const RECORD_JOIN_SCRIPT: &str = r#"
+redis.call('ZINCRBY', KEYS[1], 1, ARGV[1])
+redis.call('LPUSH', KEYS[2], ARGV[2])
+redis.call('LTRIM', KEYS[2], 0, tonumber(ARGV[3]) - 1)
+return 1
+"#;
+
+/// Top inviters as a flat `[id, count, id, count, ...]` list.
+///
+/// `KEYS[1]` is the inviter leaderboard, `ARGV[1]` the number to return.
+const TOP_INVITERS_SCRIPT: &str = r#"
+return redis.call('ZREVRANGE', KEYS[1], 0, tonumber(ARGV[1]) - 1, 'WITHSCORES')
+"#;
+
+/// Members an inviter brought in, newest first.
+///
+/// `KEYS[1]` is the inviter's joins, `ARGV[1]` the number to return.
+const INVITER_JOINS_SCRIPT: &str = r#"
+return redis.call('LRANGE', KEYS[1], 0, tonumber(ARGV[1]) - 1)
+"#;
+
+/// Replace the guild's invite snapshot.
+///
+/// `KEYS[1]` is the snapshot hash. `ARGV` holds `code, invite` pairs, each
+/// invite serialized by the caller.
+const SEED_SNAPSHOT_SCRIPT: &str = r#"
+redis.call('DEL', KEYS[1])
+for i = 1, #ARGV, 2 do
+    redis.call('HSET', KEYS[1], ARGV[i], ARGV[i + 1])
+end
+return 1
+"#;
+
+/// Store fresh invite counts without ever moving one backwards, and return
+/// the codes whose count this call advanced. Two joins racing on the same
+/// snapshot can then only credit each use once. When pruning, invites
+/// missing from the given ones are dropped unless they were on their last
+/// use, those are kept for the join that used them to claim.
+///
+/// `KEYS[1]` is the snapshot hash. `ARGV[1]` is `1` to prune, followed by
+/// `code, invite, uses` triples.
+const ADVANCE_SNAPSHOT_SCRIPT: &str = r#"
+local seen = {}
+local advanced = {}
+for i = 2, #ARGV, 3 do
+    local code = ARGV[i]
+    local uses = tonumber(ARGV[i + 2])
+    local old = redis.call('HGET', KEYS[1], code)
+    local before = 0
+    if old then
+        before = cjson.decode(old).uses
+    end
+    seen[code] = true
+    if uses > before then
+        redis.call('HSET', KEYS[1], code, ARGV[i + 1])
+        table.insert(advanced, code)
+    elseif not old then
+        redis.call('HSET', KEYS[1], code, ARGV[i + 1])
+    end
+end
+if ARGV[1] ~= '1' then
+    return advanced
+end
+local stored = redis.call('HGETALL', KEYS[1])
+for i = 1, #stored, 2 do
+    local code = stored[i]
+    if not seen[code] then
+        local invite = cjson.decode(stored[i + 1])
+        if invite.max_uses == 0 or invite.uses + 1 < invite.max_uses then
+            redis.call('HDEL', KEYS[1], code)
+        end
+    end
+end
+return advanced
+"#;
+
+/// Delete an invite only if it still has the value the caller decided on.
+/// Returns 1 when this call removed it.
+///
+/// `KEYS[1]` is the snapshot hash, `ARGV[1]` the code, `ARGV[2]` the
+/// expected value or an empty string to delete unconditionally.
+const REMOVE_INVITE_SCRIPT: &str = r#"
+if ARGV[2] ~= '' and redis.call('HGET', KEYS[1], ARGV[1]) ~= ARGV[2] then
+    return 0
+end
+return redis.call('HDEL', KEYS[1], ARGV[1])
+"#;
+
+/// Whole snapshot as a flat `[code, invite, code, invite, ...]` list.
+///
+/// `KEYS[1]` is the snapshot hash.
+const READ_SNAPSHOT_SCRIPT: &str = r#"
+return redis.call('HGETALL', KEYS[1])
+"#;
+
+#[inline]
+fn invite_snapshot_key(guild_id: &Id) -> String {
+    format!("invite_snapshot:{}", guild_id)
+}
+
+#[inline]
+fn inviter_leaderboard_key(guild_id: &Id) -> String {
+    format!("invite_leaderboard:{}", guild_id)
+}
+
+#[inline]
+fn inviter_joins_key(guild_id: &Id, inviter_id: &Id) -> String {
+    format!("invite_joins:{}:{}", guild_id, inviter_id)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TrackedInvite {
+    uses: u64,
+    /// 0 for unlimited.
+    max_uses: u64,
+    inviter_id: Option<Id>,
+}
+
+impl From<&Invite> for TrackedInvite {
+    fn from(invite: &Invite) -> Self {
+        Self {
+            uses: invite.uses.unwrap_or(0),
+            max_uses: invite.max_uses.unwrap_or(0),
+            inviter_id: invite.inviter.as_ref().map(|u| u.id),
+        }
+    }
+}
+
+impl TrackedInvite {
+    /// Whether a single use would exhaust the invite, which makes Discord
+    /// delete it, possibly before the member add arrives.
+    fn last_use(&self) -> bool {
+        self.max_uses > 0 && self.uses + 1 >= self.max_uses
+    }
+}
+
+/// Invite use counts keyed by code.
+type InviteSnapshot = HashMap<String, TrackedInvite>;
+
+/// The invite a member joined with.
+#[derive(Debug, Clone)]
+pub struct UsedInvite {
+    pub code: String,
+    pub inviter_id: Option<Id>,
+}
+
+/// Find the invite consumed between two snapshots. Only one invite can be
+/// credited, so concurrent joins that bump several invites are left unknown.
+fn find_used_invite(previous: &InviteSnapshot, current: &InviteSnapshot) -> Option<UsedInvite> {
+    let mut bumped = current.iter().filter(|(code, invite)| {
+        let before = previous.get(*code).map(|p| p.uses).unwrap_or(0);
+        invite.uses > before
+    });
+
+    if let Some((code, invite)) = bumped.next() {
+        if bumped.next().is_some() {
+            return None;
+        }
+
+        return Some(UsedInvite {
+            code: code.clone(),
+            inviter_id: invite.inviter_id,
+        });
+    }
+
+    // Nothing was bumped, so the invite may have been used up and deleted
+    let mut exhausted = previous
+        .iter()
+        .filter(|(code, invite)| invite.last_use() && !current.contains_key(*code));
+
+    match (exhausted.next(), exhausted.next()) {
+        (Some((code, invite)), None) => Some(UsedInvite {
+            code: code.clone(),
+            inviter_id: invite.inviter_id,
+        }),
+        _ => None,
+    }
+}
+
+fn encode(invite: &TrackedInvite) -> DiscordResult<String> {
+    serde_json::to_string(invite)
+        .map_err(|e| DiscordError::ParseError(format!("Failed to encode invite: {e}")))
+}
+
+impl EventHandler {
+    async fn get_invite_snapshot(&self, guild_id: &Id) -> DiscordResult<InviteSnapshot> {
+        let flat = self
+            .cache
+            .eval::<Vec<String>>(READ_SNAPSHOT_SCRIPT, &[&invite_snapshot_key(guild_id)], &[])
+            .await?;
+
+        Ok(flat
+            .chunks_exact(2)
+            .filter_map(|pair| Some((pair[0].clone(), serde_json::from_str(&pair[1]).ok()?)))
+            .collect())
+    }
+
+    async fn fetch_invite_snapshot(&self, guild_id: &Id) -> DiscordResult<InviteSnapshot> {
+        let invites = self.rest.get_guild_invites(guild_id).await?;
+
+        Ok(invites
+            .iter()
+            .map(|invite| (invite.code.to_string(), TrackedInvite::from(invite)))
+            .collect())
+    }
+
+    /// Seed the guild's invite use counts, called on guild create.
+    #[instrument(skip(self))]
+    pub async fn seed_invites(&self, guild_id: &Id) -> DiscordResult<()> {
+        let snapshot = self.fetch_invite_snapshot(guild_id).await?;
+
+        let mut args = Vec::with_capacity(snapshot.len() * 2);
+        for (code, invite) in &snapshot {
+            args.push(code.clone());
+            args.push(encode(invite)?);
+        }
+
+        self.cache
+            .eval::<u64>(
+                SEED_SNAPSHOT_SCRIPT,
+                &[&invite_snapshot_key(guild_id)],
+                &args,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    #[instrument(skip(self, invite), fields(code = %invite.code))]
+    pub async fn track_invite_create(
+        &self,
+        guild_id: &Id,
+        invite: &InviteCreateEvent,
+    ) -> DiscordResult<()> {
+        let tracked = TrackedInvite {
+            uses: invite.uses,
+            max_uses: invite.max_uses,
+            inviter_id: invite.inviter.as_ref().map(|u| u.id),
+        };
+
+        self.cache
+            .eval::<Vec<String>>(
+                ADVANCE_SNAPSHOT_SCRIPT,
+                &[&invite_snapshot_key(guild_id)],
+                &[
+                    "0".to_string(),
+                    invite.code.to_string(),
+                    encode(&tracked)?,
+                    tracked.uses.to_string(),
+                ],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    pub async fn track_invite_delete(&self, guild_id: &Id, code: &str) -> DiscordResult<()> {
+        let snapshot = self.get_invite_snapshot(guild_id).await?;
+        let Some(invite) = snapshot.get(code) else {
+            return Ok(());
+        };
+
+        // An invite on its last use is deleted when it is used, often before
+        // the member add arrives. Keep it so the join can still be credited,
+        // the next resolve drops it.
+        if invite.last_use() {
+            return Ok(());
+        }
+
+        // Only delete the entry that was checked, a concurrent update may
+        // have put it on its last use
+        self.cache
+            .eval::<u64>(
+                REMOVE_INVITE_SCRIPT,
+                &[&invite_snapshot_key(guild_id)],
+                &[code.to_string(), encode(invite)?],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Work out which invite a member joined with by diffing use counts, and
+    /// credit the inviter. Each use is claimed in Redis first, so concurrent
+    /// joins never credit the same use twice.
+    #[instrument(skip(self))]
+    pub async fn resolve_member_invite(
+        &self,
+        guild_id: &Id,
+        user_id: &Id,
+    ) -> DiscordResult<Option<UsedInvite>> {
+        let key = invite_snapshot_key(guild_id);
+        let previous = self.get_invite_snapshot(guild_id).await?;
+        let current = self.fetch_invite_snapshot(guild_id).await?;
+
+        let mut args = Vec::with_capacity(current.len() * 3 + 1);
+        args.push("1".to_string());
+        for (code, invite) in &current {
+            args.push(code.clone());
+            args.push(encode(invite)?);
+            args.push(invite.uses.to_string());
+        }
+        let advanced = self
+            .cache
+            .eval::<Vec<String>>(ADVANCE_SNAPSHOT_SCRIPT, &[&key], &args)
+            .await?;
+
+        let Some(used) = find_used_invite(&previous, &current) else {
+            return Ok(None);
+        };
+
+        let claimed = if current.contains_key(&used.code) {
+            advanced.contains(&used.code)
+        } else {
+            // Used up and deleted, whoever removes it gets the credit
+            self.cache
+                .eval::<u64>(
+                    REMOVE_INVITE_SCRIPT,
+                    &[&key],
+                    &[used.code.clone(), String::new()],
+                )
+                .await?
+                == 1
+        };
+
+        if !claimed {
+            return Ok(None);
+        }
+
+        if let Some(inviter_id) = used.inviter_id {
+            self.cache
+                .eval::<u64>(
+                    RECORD_JOIN_SCRIPT,
+                    &[
+                        &inviter_leaderboard_key(guild_id),
+                        &inviter_joins_key(guild_id, &inviter_id),
+                    ],
+                    &[
+                        inviter_id.to_string(),
+                        user_id.to_string(),
+                        INVITE_JOINS_CAP.to_string(),
+                    ],
+                )
+                .await?;
+        }
+
+        Ok(Some(used))
+    }
+
+    /// Inviters with the most tracked joins, highest first.
+    #[instrument(skip(self))]
+    pub async fn get_top_inviters(
+        &self,
+        guild_id: &Id,
+        limit: usize,
+    ) -> DiscordResult<Vec<(Id, u64)>> {
+        let flat = self
+            .cache
+            .eval::<Vec<String>>(
+                TOP_INVITERS_SCRIPT,
+                &[&inviter_leaderboard_key(guild_id)],
+                &[limit.to_string()],
+            )
+            .await?;
+
+        Ok(flat
+            .chunks_exact(2)
+            .filter_map(|pair| {
+                let id = pair[0].parse::<u64>().ok()?;
+                let count = pair[1].parse::<f64>().ok()? as u64;
+                Some((Id::new(id), count))
+            })
+            .collect())
+    }
+
+    /// Members the inviter brought in, newest first.
+    #[instrument(skip(self))]
+    pub async fn get_inviter_joins(
+        &self,
+        guild_id: &Id,
+        inviter_id: &Id,
+        limit: usize,
+    ) -> DiscordResult<Vec<Id>> {
+        let ids = self
+            .cache
+            .eval::<Vec<String>>(
+                INVITER_JOINS_SCRIPT,
+                &[&inviter_joins_key(guild_id, inviter_id)],
+                &[limit.to_string()],
+            )
+            .await?;
+
+        Ok(ids
+            .iter()
+            .filter_map(|id| id.parse::<u64>().ok().map(Id::new))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invite(uses: u64, max_uses: u64, inviter: u64) -> TrackedInvite {
+        TrackedInvite {
+            uses,
+            max_uses,
+            inviter_id: Some(Id::new(inviter)),
+        }
+    }
+
+    fn snapshot(invites: &[(&str, TrackedInvite)]) -> InviteSnapshot {
+        invites
+            .iter()
+            .map(|(code, invite)| (code.to_string(), invite.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn credits_the_single_bumped_invite() {
+        let previous = snapshot(&[("a", invite(1, 0, 1)), ("b", invite(5, 0, 2))]);
+        let current = snapshot(&[("a", invite(1, 0, 1)), ("b", invite(6, 0, 2))]);
+
+        let used = find_used_invite(&previous, &current).unwrap();
+        assert_eq!(used.code, "b");
+        assert_eq!(used.inviter_id, Some(Id::new(2)));
+    }
+
+    #[test]
+    fn new_invite_with_uses_counts_from_zero() {
+        let previous = snapshot(&[("a", invite(1, 0, 1))]);
+        let current = snapshot(&[("a", invite(1, 0, 1)), ("new", invite(1, 0, 3))]);
+
+        assert_eq!(find_used_invite(&previous, &current).unwrap().code, "new");
+    }
+
+    #[test]
+    fn several_bumped_invites_are_ambiguous() {
+        let previous = snapshot(&[("a", invite(1, 0, 1)), ("b", invite(5, 0, 2))]);
+        let current = snapshot(&[("a", invite(2, 0, 1)), ("b", invite(6, 0, 2))]);
+
+        assert!(find_used_invite(&previous, &current).is_none());
+    }
+
+    #[test]
+    fn credits_an_exhausted_invite_that_was_deleted() {
+        let previous = snapshot(&[("a", invite(1, 0, 1)), ("last", invite(4, 5, 2))]);
+        let current = snapshot(&[("a", invite(1, 0, 1))]);
+
+        let used = find_used_invite(&previous, &current).unwrap();
+        assert_eq!(used.code, "last");
+        assert_eq!(used.inviter_id, Some(Id::new(2)));
+    }
+
+    #[test]
+    fn ignores_deleted_invites_that_were_not_on_their_last_use() {
+        let previous = snapshot(&[("a", invite(1, 0, 1)), ("b", invite(2, 5, 2))]);
+        let current = snapshot(&[("a", invite(1, 0, 1))]);
+
+        assert!(find_used_invite(&previous, &current).is_none());
+    }
+
+    #[test]
+    fn several_exhausted_invites_are_ambiguous() {
+        let previous = snapshot(&[("x", invite(0, 1, 1)), ("y", invite(0, 1, 2))]);
+        let current = InviteSnapshot::new();
+
+        assert!(find_used_invite(&previous, &current).is_none());
+    }
+
+    #[test]
+    fn nothing_changed() {
+        let previous = snapshot(&[("a", invite(1, 0, 1))]);
+
+        assert!(find_used_invite(&previous, &previous).is_none());
+    }
+}
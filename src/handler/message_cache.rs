@@ -0,0 +1,234 @@
+use std::time::Duration;
+
+use bm_lib::{
+    discord::{DiscordError, DiscordResult, Id, Message},
+    model::Config,
+};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use super::EventHandler;
+
+/// How long a message stays available for delete and edit logs.
+const MESSAGE_CACHE_TTL: Duration = Duration::from_secs(60 * 60 * 24);
+/// Most recent messages kept per guild, older ones are evicted.
+const MESSAGE_CACHE_GUILD_CAP: u64 = 5000;
+/// Longest content stored, Discord allows 4000 characters with Nitro.
+const MESSAGE_CACHE_MAX_CONTENT: usize = 4000;
+
+/// Store a message and push it onto the guild index, evicting messages past
+/// the cap. Values are serialized by the caller so every script agrees on
+/// the format.
+///
+/// `KEYS[1]` is the message, `KEYS[2]` the guild index. `ARGV[1]` is the
+/// serialized message, `ARGV[2]` the TTL in ms, `ARGV[3]` the cap.
+const STORE_SCRIPT: &str = r#"
+local cap = tonumber(ARGV[3])
+redis.call('SET', KEYS[1], ARGV[1], 'PX', ARGV[2])
+redis.call('LPUSH', KEYS[2], KEYS[1])
+local evicted = redis.call('LRANGE', KEYS[2], cap, -1)
+for _, key in ipairs(evicted) do
+    redis.call('DEL', key)
+end
+redis.call('LTRIM', KEYS[2], 0, cap - 1)
+redis.call('PEXPIRE', KEYS[2], ARGV[2])
+return #evicted
+"#;
+
+/// Remove a message and return it. The stale index entry is left to age out.
+const TAKE_SCRIPT: &str = r#"
+local value = redis.call('GET', KEYS[1])
+if value then
+    redis.call('DEL', KEYS[1])
+end
+return value
+"#;
+
+/// Remove messages and return the ones that were cached, in one round trip
+/// for a bulk delete.
+///
+/// `KEYS` are the messages.
+const TAKE_MANY_SCRIPT: &str = r#"
+local values = {}
+for _, key in ipairs(KEYS) do
+    local value = redis.call('GET', key)
+    if value then
+        redis.call('DEL', key)
+        values[#values + 1] = value
+    end
+end
+return values
+"#;
+
+/// Replace a cached message, keeping its TTL, and return the previous value.
+/// Messages that were never cached are not added.
+const SWAP_SCRIPT: &str = r#"
+local value = redis.call('GET', KEYS[1])
+if value then
+    redis.call('SET', KEYS[1], ARGV[1], 'KEEPTTL')
+end
+return value
+"#;
+
+#[inline]
+fn message_cache_key(channel_id: &Id, message_id: &Id) -> String {
+    format!("message:{}:{}", channel_id, message_id)
+}
+
+#[inline]
+fn message_index_cache_key(guild_id: &Id) -> String {
+    format!("message_index:{}", guild_id)
+}
+
+/// The parts of a message kept for logging.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedMessage {
+    pub id: Id,
+    pub channel_id: Id,
+    pub author_id: Id,
+    pub author_name: String,
+    pub content: String,
+    pub attachments: Vec<String>,
+    /// When the message was sent, in ms since the epoch.
+    pub created_at: u64,
+}
+
+impl CachedMessage {
+    fn from_message(message: &Message) -> Option<Self> {
+        let author = message.author.as_ref()?;
+
+        Some(Self {
+            id: message.id,
+            channel_id: message.channel_id,
+            author_id: author.id,
+            author_name: author.username.to_string(),
+            content: message
+                .content
+                .chars()
+                .take(MESSAGE_CACHE_MAX_CONTENT)
+                .collect(),
+            attachments: message
+                .attachments
+                .iter()
+                .map(|attachment| attachment.filename.to_string())
+                .collect(),
+            created_at: bm_lib::util::snowflake_to_timestamp(message.id),
+        })
+    }
+}
+
+fn encode(message: &CachedMessage) -> DiscordResult<String> {
+    serde_json::to_string(message)
+        .map_err(|e| DiscordError::ParseError(format!("Failed to encode cached message: {e}")))
+}
+
+fn decode(value: Option<String>) -> Option<CachedMessage> {
+    value.and_then(|value| serde_json::from_str(&value).ok())
+}
+
+impl EventHandler {
+    /// Whether messages in this channel may be cached for logging. Follows
+    /// the same channel ignores as the message logs.
+    pub async fn message_cache_enabled(
+        &self,
+        config: &Config,
+        guild_id: &Id,
+        channel_id: &Id,
+    ) -> bool {
+        config.logging_enabled
+            && config.message_cache_enabled
+            && !self
+                .is_channel_log_ignored(config, guild_id, channel_id)
+                .await
+    }
+
+    #[instrument(skip(self, message), fields(message_id = %message.id))]
+    pub async fn cache_message(&self, guild_id: &Id, message: &Message) -> DiscordResult<()> {
+        let Some(cached) = CachedMessage::from_message(message) else {
+            return Ok(());
+        };
+
+        let key = message_cache_key(&message.channel_id, &message.id);
+        let index_key = message_index_cache_key(guild_id);
+        let value = encode(&cached)?;
+
+        self.cache
+            .eval::<u64>(
+                STORE_SCRIPT,
+                &[&key, &index_key],
+                &[
+                    value,
+                    MESSAGE_CACHE_TTL.as_millis().to_string(),
+                    MESSAGE_CACHE_GUILD_CAP.to_string(),
+                ],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Remove a deleted message from the cache and return what was stored.
+    #[instrument(skip(self))]
+    pub async fn take_cached_message(
+        &self,
+        channel_id: &Id,
+        message_id: &Id,
+    ) -> DiscordResult<Option<CachedMessage>> {
+        let key = message_cache_key(channel_id, message_id);
+        let value = self
+            .cache
+            .eval::<Option<String>>(TAKE_SCRIPT, &[&key], &[])
+            .await?;
+
+        Ok(decode(value))
+    }
+
+    /// Remove bulk deleted messages from the cache and return the ones that
+    /// were stored. Messages that were never cached are left out.
+    #[instrument(skip(self, message_ids), fields(count = message_ids.len()))]
+    pub async fn take_cached_messages(
+        &self,
+        channel_id: &Id,
+        message_ids: &[Id],
+    ) -> DiscordResult<Vec<CachedMessage>> {
+        if message_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let keys: Vec<String> = message_ids
+            .iter()
+            .map(|message_id| message_cache_key(channel_id, message_id))
+            .collect();
+        let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+
+        let values = self
+            .cache
+            .eval::<Vec<String>>(TAKE_MANY_SCRIPT, &keys, &[])
+            .await?;
+
+        Ok(values
+            .into_iter()
+            .filter_map(|value| decode(Some(value)))
+            .collect())
+    }
+
+    /// Store the edited version of a cached message and return the previous
+    /// version, if it was cached.
+    #[instrument(skip(self, message), fields(message_id = %message.id))]
+    pub async fn update_cached_message(
+        &self,
+        message: &Message,
+    ) -> DiscordResult<Option<CachedMessage>> {
+        let Some(cached) = CachedMessage::from_message(message) else {
+            return Ok(None);
+        };
+
+        let key = message_cache_key(&message.channel_id, &message.id);
+        let value = self
+            .cache
+            .eval::<Option<String>>(SWAP_SCRIPT, &[&key], &[encode(&cached)?])
+            .await?;
+
+        Ok(decode(value))
+    }
+}
@@ -212,6 +212,51 @@ impl EventHandler {
         Ok(())
     }
 
+    /// Explain the minimum account age to a member who is about to be removed
+    /// for not meeting it. `message` replaces the default description.
+    #[instrument(skip(self, message))]
+    pub async fn send_account_age_dm(
+        &self,
+        guild_id: &Id,
+        user_id: &Id,
+        eligible_at: i64,
+        message: Option<&str>,
+    ) -> DiscordResult<()> {
+        let Ok(channel_id) = self.get_user_dm_channel(user_id).await else {
+            return Ok(()); // Can't send DM
+        };
+
+        let guild_name = match self.get_guild(guild_id).await {
+            Ok(guild) => guild.name.to_string(),
+            Err(e) => {
+                tracing::warn!("Failed to get guild for account age DM: {}", e);
+                guild_id.to_string()
+            }
+        };
+
+        let description = message.map(str::to_string).unwrap_or_else(|| {
+            format!("{guild_name} requires accounts to be older than yours to join")
+        });
+
+        let embed = EmbedBuilder::new()
+            .title(format!("Your account is too new to join {guild_name}").as_str())
+            .description(description.as_str())
+            .field(
+                "You can join",
+                format!("<t:{}:R>", eligible_at / 1000).as_str(),
+                true,
+            )
+            .color(0xFF8C00)
+            .footer(format!("{SERVICE_NAME} by {AUTHOR_COLON_THREE}"), None)
+            .build();
+
+        self.rest
+            .create_message_with_embed(&channel_id, &[embed])
+            .await?;
+
+        Ok(())
+    }
+
     #[instrument(skip(self, infraction), fields(user_id = infraction.user_id.get()))]
     pub async fn send_infraction_remove_dm(&self, infraction: &Infraction) -> DiscordResult<()> {
         let channel_id = match self.get_user_dm_channel(&infraction.user_id).await {
@@ -1,9 +1,13 @@
+pub mod audit;
+pub mod claim;
 pub mod data;
 pub mod groups;
 pub mod handler;
 pub mod help;
+pub mod invites;
 pub mod macros;
 pub mod mesastream;
+pub mod message_cache;
 pub mod messages;
 pub mod moderation;
 pub mod permissions;
@@ -19,13 +23,21 @@ use bm_lib::{
 };
 use tokio::sync::Mutex;
 
+use crate::{
+    automod::PhishingDatabase,
+    logging::{EventExporter, LogBatcher, VoiceLogQueue},
+};
+
 pub const ZWSP: &str = "\u{200B}";
 
+/// Cheap to clone, every field is a shared handle.
+#[derive(Clone)]
 pub struct EventHandler {
     pub rest: Arc<DiscordRestClient>,
     pub cache: Arc<Cache<RedisCache>>,
     pub db: Arc<Database>,
     pub mesastream: Arc<MesastreamClient>,
+    pub phishing: Arc<PhishingDatabase>,
     pub gateway: Arc<Mutex<Option<GatewaySender>>>,
 
     pub start_time: std::time::Instant,
@@ -37,6 +49,13 @@ pub struct EventHandler {
     /// Guild IDs where the bot is currently in a voice channel.
     /// Used to recreate mesastream players after mesastream restarts.
     pub voice_guilds: Arc<Mutex<Vec<Id>>>,
+
+    /// Queue that coalesces log messages before they are sent.
+    pub log_batcher: Arc<LogBatcher>,
+    /// Voice state updates waiting to be logged, in arrival order.
+    pub voice_log: Arc<VoiceLogQueue>,
+    /// Structured export of every log event, independent of log channels.
+    pub exporter: Arc<EventExporter>,
 }
 
 impl EventHandler {
@@ -45,18 +64,24 @@ impl EventHandler {
         cache: Arc<Cache<RedisCache>>,
         db: Arc<Database>,
         mesastream: Arc<MesastreamClient>,
+        phishing: Arc<PhishingDatabase>,
+        exporter: Arc<EventExporter>,
     ) -> Self {
         Self {
             rest,
             cache,
             db,
             mesastream,
+            phishing,
             start_time: std::time::Instant::now(),
             ping_nanos: Arc::new(AtomicU64::new(0)),
             bot_mention: Arc::new(OnceLock::new()),
             bot_id: Arc::new(OnceLock::new()),
             gateway: Arc::new(Mutex::new(None)),
             voice_guilds: Arc::new(Mutex::new(Vec::new())),
+            log_batcher: Arc::new(LogBatcher::new()),
+            voice_log: Arc::new(VoiceLogQueue::new()),
+            exporter,
         }
     }
 }
@@ -1,7 +1,7 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, collections::HashSet, time::Duration};
 
 use bm_lib::{
-    discord::{DiscordError, DiscordResult, Id},
+    discord::{DiscordError, DiscordResult, Guild, Id},
     model::{
         logging::LogEvent,
         Infraction, Uuid,
@@ -14,6 +14,61 @@ use super::EventHandler;
 
 pub const DEFAULT_WARN_LENGTH: u64 = 604800;
 
+/// Longest a member's quarantine claim is held, in case its shard dies
+/// between stripping roles and recording the infraction.
+const QUARANTINE_CLAIM_TTL: Duration = Duration::from_secs(30);
+
+#[inline]
+fn quarantine_claim_key(guild_id: &Id, user_id: &Id) -> String {
+    format!("quarantine_claim:{}:{}", guild_id, user_id)
+}
+
+/// Result of quarantining a member.
+#[derive(Debug)]
+pub enum QuarantineOutcome {
+    /// Roles were stripped and this infraction recorded.
+    Created(Infraction),
+    /// The member was already quarantined by this infraction.
+    Existing(Infraction),
+    /// Another quarantine of the member is in progress.
+    Pending,
+}
+
+/// Roles Discord assigns itself (boosts, integrations, bots). They can't be
+/// added or removed by us, so they stay on a quarantined member and are never
+/// stored.
+fn managed_role_ids(guild: &Guild) -> HashSet<Id> {
+    guild
+        .roles
+        .iter()
+        .filter(|role| role.managed)
+        .map(|role| role.id)
+        .collect()
+}
+
+/// Roles to give a member leaving quarantine: the stored roles that still
+/// exist, plus whatever managed roles they hold now.
+pub(crate) fn restored_roles(guild: &Guild, stored: &[Id], current: &[Id]) -> Vec<Id> {
+    let managed = managed_role_ids(guild);
+
+    let mut roles: Vec<Id> = current
+        .iter()
+        .filter(|role| managed.contains(role))
+        .copied()
+        .collect();
+
+    for role in stored {
+        if !managed.contains(role)
+            && !roles.contains(role)
+            && guild.roles.iter().any(|r| r.id == *role)
+        {
+            roles.push(*role);
+        }
+    }
+
+    roles
+}
+
 impl EventHandler {
     #[instrument(skip(self))]
     pub async fn kick_user(
@@ -145,6 +200,185 @@ impl EventHandler {
         Ok(infraction)
     }
 
+    /// Replace a member's roles with the quarantine role and return the roles
+    /// they had, so they can be restored later. Managed roles are left alone.
+    #[instrument(skip(self))]
+    pub async fn strip_member_roles(
+        &self,
+        guild_id: &Id,
+        user_id: &Id,
+        quarantine_role: &Id,
+        reason: Option<Cow<'_, str>>,
+    ) -> DiscordResult<Vec<Id>> {
+        let (guild, roles) = tokio::try_join!(
+            self.get_guild(guild_id),
+            self.get_member_roles(guild_id, user_id)
+        )?;
+        let managed = managed_role_ids(&guild);
+
+        let (mut kept, stored_roles): (Vec<Id>, Vec<Id>) = roles
+            .into_iter()
+            .filter(|role| role != quarantine_role)
+            .partition(|role| managed.contains(role));
+        kept.push(*quarantine_role);
+
+        self.rest
+            .modify_member_roles(guild_id, user_id, &kept, reason)
+            .await?;
+        self.set_member_roles(guild_id, user_id, &kept).await?;
+
+        Ok(stored_roles)
+    }
+
+    /// The member's active quarantine, if they are already quarantined.
+    #[instrument(skip(self))]
+    pub async fn get_active_quarantine(
+        &self,
+        guild_id: &Id,
+        user_id: &Id,
+    ) -> DiscordResult<Option<Infraction>> {
+        let infractions = self
+            .db
+            .get_active_infractions(
+                guild_id,
+                user_id,
+                Some(bm_lib::model::InfractionType::Quarantine),
+            )
+            .await?;
+
+        Ok(infractions.into_iter().next())
+    }
+
+    /// Strip a member's roles and record `infraction` with the roles they had.
+    ///
+    /// Runs under a per-member claim, so parallel quarantines (automod hits on
+    /// several messages, a moderator at the same time) strip and record once.
+    /// A member who is already quarantined keeps their existing infraction,
+    /// stripping again would store only the quarantine role and lose their
+    /// real roles.
+    #[instrument(skip(self, infraction), fields(infraction_id = %infraction.uuid))]
+    pub async fn record_quarantine(
+        &self,
+        guild_id: &Id,
+        user_id: &Id,
+        quarantine_role: &Id,
+        infraction: Infraction,
+        reason: Option<Cow<'_, str>>,
+    ) -> DiscordResult<QuarantineOutcome> {
+        let key = quarantine_claim_key(guild_id, user_id);
+        if !self.claim(&key, QUARANTINE_CLAIM_TTL).await? {
+            return Ok(QuarantineOutcome::Pending);
+        }
+
+        let result = self
+            .record_quarantine_claimed(guild_id, user_id, quarantine_role, infraction, reason)
+            .await;
+
+        self.release_claim(&key).await;
+
+        result
+    }
+
+    /// [`Self::record_quarantine`] once the member's claim is held.
+    async fn record_quarantine_claimed(
+        &self,
+        guild_id: &Id,
+        user_id: &Id,
+        quarantine_role: &Id,
+        mut infraction: Infraction,
+        reason: Option<Cow<'_, str>>,
+    ) -> DiscordResult<QuarantineOutcome> {
+        if let Some(existing) = self.get_active_quarantine(guild_id, user_id).await? {
+            return Ok(QuarantineOutcome::Existing(existing));
+        }
+
+        // Roles have to be stripped first so the infraction records what to restore
+        infraction.stored_roles = Some(
+            self.strip_member_roles(guild_id, user_id, quarantine_role, reason)
+                .await?,
+        );
+        self.db.create_infraction(&infraction).await?;
+
+        Ok(QuarantineOutcome::Created(infraction))
+    }
+
+    #[instrument(skip(self))]
+    pub async fn quarantine_user(
+        &self,
+        guild_id: &Id,
+        user_id: &Id,
+        moderator_id: &Id,
+        quarantine_role: &Id,
+        duration: Option<u64>,
+        reason: Option<Cow<'_, str>>,
+    ) -> DiscordResult<QuarantineOutcome> {
+        let reason_str = reason
+            .as_ref()
+            .map(|r| r.as_ref().to_string())
+            .unwrap_or_else(|| "No reason".into());
+
+        let infraction = Infraction::new(
+            *guild_id,
+            *user_id,
+            *moderator_id,
+            bm_lib::model::InfractionType::Quarantine,
+            reason.as_ref().map(|r| r.to_string()),
+            duration.map(duration_to_unix_timestamp),
+            true,
+        );
+
+        let outcome = self
+            .record_quarantine(guild_id, user_id, quarantine_role, infraction, reason)
+            .await?;
+        let QuarantineOutcome::Created(infraction) = &outcome else {
+            return Ok(outcome);
+        };
+
+        self.send_infraction_dm(infraction).await?;
+
+        let _ = self
+            .log_event(LogEvent::ModerationQuarantine {
+                guild_id: *guild_id,
+                user_id: *user_id,
+                moderator_id: *moderator_id,
+                reason: reason_str,
+                duration,
+                infraction_id: infraction.uuid,
+            })
+            .await;
+
+        Ok(outcome)
+    }
+
+    /// Give a quarantined member back the roles stored on the infraction.
+    /// Infractions without stored roles are left alone rather than clearing
+    /// the member's roles.
+    #[instrument(skip(self, infraction), fields(infraction_id = %infraction.uuid))]
+    async fn restore_quarantined_roles(
+        &self,
+        infraction: &Infraction,
+        reason: Option<Cow<'_, str>>,
+    ) -> DiscordResult<()> {
+        let Some(stored) = infraction.stored_roles.as_ref() else {
+            tracing::warn!("Quarantine has no stored roles, not restoring");
+            return Ok(());
+        };
+
+        let (guild, current) = tokio::try_join!(
+            self.get_guild(&infraction.guild_id),
+            self.get_member_roles(&infraction.guild_id, &infraction.user_id)
+        )?;
+        let roles = restored_roles(&guild, stored, &current);
+
+        self.rest
+            .modify_member_roles(&infraction.guild_id, &infraction.user_id, &roles, reason)
+            .await?;
+        self.set_member_roles(&infraction.guild_id, &infraction.user_id, &roles)
+            .await?;
+
+        Ok(())
+    }
+
     #[instrument(skip(self))]
     pub async fn warn_user(
         &self,
@@ -276,6 +510,55 @@ impl EventHandler {
         Ok(())
     }
 
+    #[instrument(skip(self))]
+    pub async fn unquarantine_user(
+        &self,
+        guild_id: &Id,
+        user_id: &Id,
+        moderator_id: &Id,
+        reason: Option<Cow<'_, str>>,
+    ) -> DiscordResult<()> {
+        let infractions = self
+            .db
+            .get_active_infractions(
+                guild_id,
+                user_id,
+                Some(bm_lib::model::InfractionType::Quarantine),
+            )
+            .await?;
+
+        // Older duplicate quarantines can hold a second, empty role list, so
+        // roles are restored once from the one that stored the most
+        if let Some(infraction) = infractions
+            .iter()
+            .filter(|i| i.stored_roles.is_some())
+            .max_by_key(|i| i.stored_roles.as_ref().map(Vec::len))
+        {
+            self.restore_quarantined_roles(infraction, reason.clone())
+                .await?;
+        }
+
+        for infraction in infractions {
+            let (dm_result, db_result) = tokio::join!(
+                self.send_infraction_remove_dm(&infraction),
+                self.db.deactivate_infraction(&infraction.uuid)
+            );
+            dm_result?;
+            db_result?;
+        }
+
+        let _ = self
+            .log_event(LogEvent::ModerationUnquarantine {
+                guild_id: *guild_id,
+                user_id: *user_id,
+                moderator_id: *moderator_id,
+                reason: reason.as_deref().unwrap_or("No reason").to_string(),
+            })
+            .await;
+
+        Ok(())
+    }
+
     #[instrument(skip(self))]
     pub async fn pardon(
         &self,
@@ -291,6 +574,13 @@ impl EventHandler {
             .map_err(DiscordError::from)?;
 
         if let Some(ref infraction) = result {
+            if infraction.active
+                && infraction.infraction_type == bm_lib::model::InfractionType::Quarantine
+            {
+                self.restore_quarantined_roles(infraction, reason.clone())
+                    .await?;
+            }
+
             let _ = self
                 .log_event(LogEvent::ModerationPardon {
                     guild_id: *guild_id,
@@ -1,4 +1,4 @@
-use super::EventHandler;
+use super::{groups::member_groups, EventHandler};
 use bm_lib::{
     discord::{commands::Ctx, DiscordResult, Id},
     model::Config,
@@ -27,14 +27,8 @@ impl EventHandler {
             Permission::empty()
         };
 
-        if let Some(groups) = &config.permission_groups {
-            for group in groups {
-                if group.users.contains(&user_id)
-                    || group.roles.iter().any(|role| member_roles.contains(role))
-                {
-                    perms |= group.permissions;
-                }
-            }
+        for group in member_groups(config, &user_id, member_roles) {
+            perms |= group.permissions;
         }
 
         Ok(perms)
@@ -368,6 +368,8 @@ impl EventHandler {
         self.set_voice_state_channel(&guild_id, &vs.user_id, vs.channel_id.as_ref())
             .await?;
 
+        self.voice_log.enqueue(guild_id, vs);
+
         // Cache session_id for the bot user (needed for connection updates).
         // Clear it when the bot leaves a channel so stale sessions are not reused.
         if self
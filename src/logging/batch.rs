@@ -0,0 +1,349 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex as StdMutex},
+    time::Duration,
+};
+
+use bm_lib::discord::{DiscordRestClient, Embed, Id};
+use tokio::{
+    sync::mpsc::{self, error::TrySendError},
+    time::Instant,
+};
+use tracing::instrument;
+
+use crate::{handler::EventHandler, logging::LogAttachment};
+
+/// How long to wait for more events before sending a batch.
+const BATCH_WINDOW: Duration = Duration::from_millis(1500);
+
+/// Events held before new ones are dropped.
+const QUEUE_CAPACITY: usize = 2048;
+
+/// Events held for a single destination before new ones for it are dropped.
+const DESTINATION_QUEUE_CAPACITY: usize = 256;
+
+/// A destination's worker stops after this long without messages and is
+/// started again by the next one.
+const DESTINATION_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+// Discord limits for a single message
+const MAX_EMBEDS_PER_MESSAGE: usize = 10;
+const MAX_EMBED_CHARS_PER_MESSAGE: usize = 6000;
+
+/// Where a log message is delivered.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub enum LogDestination {
+    Channel(Id),
+    Webhook { id: Id, token: String },
+}
+
+// Webhook tokens are credentials, keep them out of logs and spans
+impl std::fmt::Debug for LogDestination {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Channel(id) => f.debug_tuple("Channel").field(id).finish(),
+            Self::Webhook { id, .. } => f.debug_struct("Webhook").field("id", id).finish(),
+        }
+    }
+}
+
+impl LogDestination {
+    /// Parse a `https://discord.com/api/webhooks/{id}/{token}` URL.
+    pub fn from_webhook_url(raw: &str) -> Option<Self> {
+        let url = url::Url::parse(raw).ok()?;
+
+        match url.host_str()? {
+            "discord.com" | "discordapp.com" | "canary.discord.com" | "ptb.discord.com" => {}
+            _ => return None,
+        }
+
+        let mut segments = url.path_segments()?;
+        if segments.next()? != "api" {
+            return None;
+        }
+
+        // Versioned URLs look like /api/v10/webhooks/...
+        let mut next = segments.next()?;
+        if next.starts_with('v') && next[1..].chars().all(|c| c.is_ascii_digit()) {
+            next = segments.next()?;
+        }
+        if next != "webhooks" {
+            return None;
+        }
+
+        let id = segments.next()?.parse::<u64>().ok()?;
+        let token = segments.next().filter(|t| !t.is_empty())?;
+
+        Some(Self::Webhook {
+            id: Id::new(id),
+            token: token.to_string(),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(super) enum LogPayload {
+    /// An embed and its character count towards the per-message limit.
+    Embed {
+        embed: Embed,
+        chars: usize,
+    },
+    Text(String),
+}
+
+#[derive(Debug)]
+pub(super) struct QueuedLog {
+    pub destination: LogDestination,
+    pub payload: LogPayload,
+    pub attachment: Option<LogAttachment>,
+}
+
+/// Coalesces log messages sent within [`BATCH_WINDOW`] into as few Discord
+/// messages as possible, in the order they were queued.
+///
+/// A single router task hands messages to one worker per destination, each
+/// with its own queue and batch window. A slow or dead channel or webhook
+/// only backs up, and drops from, its own queue.
+pub struct LogBatcher {
+    tx: mpsc::Sender<QueuedLog>,
+    rx: StdMutex<Option<mpsc::Receiver<QueuedLog>>>,
+    dropped: StdMutex<HashMap<LogDestination, u64>>,
+}
+
+impl Default for LogBatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LogBatcher {
+    pub fn new() -> Self {
+        let (tx, rx) = mpsc::channel(QUEUE_CAPACITY);
+
+        Self {
+            tx,
+            rx: StdMutex::new(Some(rx)),
+            dropped: StdMutex::new(HashMap::new()),
+        }
+    }
+
+    /// Queue a log message, counting it as dropped if the queue is full.
+    pub(super) fn enqueue(&self, item: QueuedLog) {
+        if let Err(TrySendError::Full(item)) = self.tx.try_send(item) {
+            self.record_dropped(item.destination);
+        }
+    }
+
+    fn record_dropped(&self, destination: LogDestination) {
+        tracing::warn!(destination = ?destination, "log queue full, dropping event");
+
+        let mut dropped = self.dropped.lock().unwrap_or_else(|e| e.into_inner());
+        *dropped.entry(destination).or_default() += 1;
+    }
+
+    fn take_dropped(&self, destination: &LogDestination) -> Option<u64> {
+        let mut dropped = self.dropped.lock().unwrap_or_else(|e| e.into_inner());
+        dropped.remove(destination)
+    }
+
+    /// Route queued messages to their destination's worker, starting workers
+    /// as needed.
+    async fn run(self: Arc<Self>, rest: Arc<DiscordRestClient>, mut rx: mpsc::Receiver<QueuedLog>) {
+        let mut workers: HashMap<LogDestination, mpsc::Sender<QueuedLog>> = HashMap::new();
+
+        while let Some(mut item) = rx.recv().await {
+            if let Some(tx) = workers.get(&item.destination) {
+                match tx.try_send(item) {
+                    Ok(()) => continue,
+                    Err(TrySendError::Full(item)) => {
+                        self.record_dropped(item.destination);
+                        continue;
+                    }
+                    // The worker went idle and stopped
+                    Err(TrySendError::Closed(returned)) => item = returned,
+                }
+            }
+
+            let destination = item.destination.clone();
+            let (tx, worker_rx) = mpsc::channel(DESTINATION_QUEUE_CAPACITY);
+            tokio::spawn(Arc::clone(&self).run_destination(
+                Arc::clone(&rest),
+                destination.clone(),
+                worker_rx,
+            ));
+
+            let _ = tx.try_send(item);
+            workers.insert(destination, tx);
+        }
+    }
+
+    /// Batch and send one destination's messages until it has been idle for
+    /// [`DESTINATION_IDLE_TIMEOUT`].
+    async fn run_destination(
+        self: Arc<Self>,
+        rest: Arc<DiscordRestClient>,
+        destination: LogDestination,
+        mut rx: mpsc::Receiver<QueuedLog>,
+    ) {
+        let mut pending = Vec::new();
+
+        loop {
+            match tokio::time::timeout(DESTINATION_IDLE_TIMEOUT, rx.recv()).await {
+                Ok(Some(first)) => pending.push(first),
+                Ok(None) => return,
+                Err(_) => {
+                    // Stop taking messages, then send anything that slipped in
+                    rx.close();
+                    while let Ok(item) = rx.try_recv() {
+                        pending.push(item);
+                    }
+                    if !pending.is_empty() {
+                        self.flush(&rest, &destination, pending).await;
+                    }
+                    return;
+                }
+            }
+
+            let deadline = Instant::now() + BATCH_WINDOW;
+            while pending.len() < DESTINATION_QUEUE_CAPACITY {
+                match tokio::time::timeout_at(deadline, rx.recv()).await {
+                    Ok(Some(item)) => pending.push(item),
+                    Ok(None) | Err(_) => break,
+                }
+            }
+
+            self.flush(&rest, &destination, std::mem::take(&mut pending))
+                .await;
+        }
+    }
+
+    /// Send everything collected for a destination in one window, followed by
+    /// a notice if any of its messages were dropped.
+    #[instrument(skip(self, rest, destination, items), fields(count = items.len()))]
+    async fn flush(
+        &self,
+        rest: &DiscordRestClient,
+        destination: &LogDestination,
+        mut items: Vec<QueuedLog>,
+    ) {
+        if let Some(count) = self.take_dropped(destination) {
+            items.push(QueuedLog {
+                destination: destination.clone(),
+                payload: LogPayload::Text(format!(
+                    "{count} log event(s) were dropped because the log queue was full"
+                )),
+                attachment: None,
+            });
+        }
+
+        send_group(rest, destination, items).await;
+    }
+}
+
+/// Send a single log message straight away, bypassing the queue.
+pub(super) async fn send_now(rest: &DiscordRestClient, item: QueuedLog) {
+    let destination = item.destination.clone();
+    send_group(rest, &destination, vec![item]).await;
+}
+
+/// Send one destination's messages, packing consecutive embeds together.
+async fn send_group(rest: &DiscordRestClient, destination: &LogDestination, items: Vec<QueuedLog>) {
+    let mut embeds: Vec<Embed> = Vec::new();
+    let mut chars = 0;
+
+    for item in items {
+        match (item.payload, item.attachment) {
+            (LogPayload::Embed { embed, chars: len }, None) => {
+                if embeds.len() == MAX_EMBEDS_PER_MESSAGE
+                    || (!embeds.is_empty() && chars + len > MAX_EMBED_CHARS_PER_MESSAGE)
+                {
+                    send(rest, destination, None, &std::mem::take(&mut embeds), None).await;
+                    chars = 0;
+                }
+
+                embeds.push(embed);
+                chars += len;
+            }
+            (payload, attachment) => {
+                if !embeds.is_empty() {
+                    send(rest, destination, None, &std::mem::take(&mut embeds), None).await;
+                    chars = 0;
+                }
+
+                match payload {
+                    LogPayload::Embed { embed, .. } => {
+                        send(rest, destination, None, &[embed], attachment.as_ref()).await
+                    }
+                    LogPayload::Text(content) => {
+                        send(rest, destination, Some(&content), &[], attachment.as_ref()).await
+                    }
+                }
+            }
+        }
+    }
+
+    if !embeds.is_empty() {
+        send(rest, destination, None, &embeds, None).await;
+    }
+}
+
+async fn send(
+    rest: &DiscordRestClient,
+    destination: &LogDestination,
+    content: Option<&str>,
+    embeds: &[Embed],
+    attachment: Option<&LogAttachment>,
+) {
+    match destination {
+        LogDestination::Channel(channel_id) => match (content, attachment) {
+            (Some(content), Some(file)) => {
+                rest.create_message_with_file_and_forget(
+                    channel_id,
+                    content,
+                    &file.filename,
+                    &file.data,
+                )
+                .await
+            }
+            (Some(content), None) => rest.create_message_and_forget(channel_id, content).await,
+            (None, Some(file)) => {
+                rest.create_message_with_embed_and_file_and_forget(
+                    channel_id,
+                    embeds,
+                    &file.filename,
+                    &file.data,
+                )
+                .await
+            }
+            (None, None) => {
+                rest.create_message_with_embed_and_forget(channel_id, embeds)
+                    .await
+            }
+        },
+        LogDestination::Webhook { id, token } => {
+            let file = attachment.map(|f| (f.filename.as_str(), f.data.as_slice()));
+            if let Err(e) = rest.execute_webhook(id, token, content, embeds, file).await {
+                tracing::warn!(webhook_id = %id, error = ?e, "failed to execute log webhook");
+            }
+        }
+    }
+}
+
+impl EventHandler {
+    /// Start delivering queued log messages. Must be called once at startup.
+    pub fn spawn_log_batcher(&self) {
+        let rx = self
+            .log_batcher
+            .rx
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take();
+
+        let Some(rx) = rx else {
+            tracing::warn!("log batcher already started");
+            return;
+        };
+
+        tokio::spawn(Arc::clone(&self.log_batcher).run(Arc::clone(&self.rest), rx));
+    }
+}
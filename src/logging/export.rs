@@ -0,0 +1,408 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use bm_lib::discord::Id;
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use tokio::{
+    fs,
+    io::{AsyncWriteExt, BufWriter},
+    sync::mpsc,
+    time::Instant,
+};
+
+/// Records held per sink before new ones are dropped.
+const EXPORT_QUEUE_CAPACITY: usize = 4096;
+
+/// Rotated files kept next to the active one, `events.jsonl.1` being newest.
+const EXPORT_FILE_ROTATIONS: usize = 5;
+
+/// How often buffered file writes are flushed to disk.
+const FILE_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+const HTTP_BATCH_SIZE: usize = 100;
+const HTTP_FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+const HTTP_MAX_ATTEMPTS: u32 = 5;
+const HTTP_RETRY_BASE: Duration = Duration::from_millis(500);
+
+/// Header carrying `sha256=<hex>`, an HMAC of `<timestamp>.<body>`.
+const SIGNATURE_HEADER: &str = "X-Signature-256";
+/// Header carrying the unix timestamp (ms) that was signed.
+const TIMESTAMP_HEADER: &str = "X-Signature-Timestamp";
+
+/// Where exported events go. Both sinks are optional and independent.
+#[derive(Debug, Clone, Default)]
+pub struct ExportConfig {
+    /// Added to the file name so every shard writes its own file.
+    pub shard_id: u32,
+    pub file_path: Option<PathBuf>,
+    pub file_max_bytes: u64,
+    pub http_url: Option<String>,
+    pub http_secret: Option<String>,
+}
+
+/// One exported event, written as a JSON line or an element of a POSTed
+/// array.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportRecord {
+    /// Unix timestamp in milliseconds.
+    pub timestamp: i64,
+    pub guild_id: Id,
+    pub event: String,
+    pub data: HashMap<String, String>,
+}
+
+/// Fans log events out to the configured export sinks. Each sink runs in its
+/// own task so a slow endpoint never holds up file writes or logging.
+pub struct EventExporter {
+    sinks: Vec<mpsc::Sender<ExportRecord>>,
+}
+
+impl EventExporter {
+    /// Start a task for every configured sink.
+    pub fn spawn(config: ExportConfig) -> Self {
+        let mut sinks = Vec::new();
+
+        if let Some(path) = config.file_path {
+            let (tx, rx) = mpsc::channel(EXPORT_QUEUE_CAPACITY);
+            tokio::spawn(run_file_sink(
+                shard_file_path(&path, config.shard_id),
+                config.file_max_bytes,
+                rx,
+            ));
+            sinks.push(tx);
+        }
+
+        if let Some(url) = config.http_url {
+            let (tx, rx) = mpsc::channel(EXPORT_QUEUE_CAPACITY);
+            tokio::spawn(run_http_sink(url, config.http_secret, rx));
+            sinks.push(tx);
+        }
+
+        Self { sinks }
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.sinks.is_empty()
+    }
+
+    pub fn export(&self, record: ExportRecord) {
+        for sink in &self.sinks {
+            if sink.try_send(record.clone()).is_err() {
+                tracing::warn!(event = %record.event, "export queue full, dropping event");
+            }
+        }
+    }
+}
+
+/// Active export file, kept open between writes. Its size is tracked here so
+/// rotation doesn't need a stat per record.
+struct FileSink {
+    path: PathBuf,
+    max_bytes: u64,
+    writer: Option<BufWriter<fs::File>>,
+    size: u64,
+}
+
+impl FileSink {
+    fn new(path: PathBuf, max_bytes: u64) -> Self {
+        Self {
+            path,
+            max_bytes,
+            writer: None,
+            size: 0,
+        }
+    }
+
+    async fn write(&mut self, record: &ExportRecord) -> std::io::Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+
+        if self.writer.is_none() {
+            self.open().await?;
+        }
+
+        if self.max_bytes > 0 && self.size > 0 && self.size + line.len() as u64 > self.max_bytes {
+            self.close().await?;
+            rotate(&self.path).await?;
+            self.open().await?;
+        }
+
+        let Some(writer) = self.writer.as_mut() else {
+            return Ok(());
+        };
+
+        if let Err(e) = writer.write_all(&line).await {
+            // Reopen on the next record rather than writing after a partial line
+            self.writer = None;
+            return Err(e);
+        }
+        self.size += line.len() as u64;
+
+        Ok(())
+    }
+
+    async fn open(&mut self) -> std::io::Result<()> {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+
+        self.size = file.metadata().await?.len();
+        self.writer = Some(BufWriter::new(file));
+
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> std::io::Result<()> {
+        match self.writer.as_mut() {
+            Some(writer) => writer.flush().await,
+            None => Ok(()),
+        }
+    }
+
+    async fn close(&mut self) -> std::io::Result<()> {
+        self.flush().await?;
+        self.writer = None;
+
+        Ok(())
+    }
+}
+
+async fn run_file_sink(path: PathBuf, max_bytes: u64, mut rx: mpsc::Receiver<ExportRecord>) {
+    let mut sink = FileSink::new(path, max_bytes);
+    let mut flush = tokio::time::interval(FILE_FLUSH_INTERVAL);
+
+    loop {
+        tokio::select! {
+            record = rx.recv() => {
+                let Some(record) = record else {
+                    break;
+                };
+
+                if let Err(e) = sink.write(&record).await {
+                    tracing::warn!(path = %sink.path.display(), error = ?e, "failed to export event to file");
+                }
+            }
+            _ = flush.tick() => {
+                if let Err(e) = sink.flush().await {
+                    tracing::warn!(path = %sink.path.display(), error = ?e, "failed to flush export file");
+                }
+            }
+        }
+    }
+
+    if let Err(e) = sink.close().await {
+        tracing::warn!(path = %sink.path.display(), error = ?e, "failed to flush export file");
+    }
+}
+
+/// Export file for a shard, `events.jsonl` becoming `events.shard-3.jsonl`,
+/// so shards sharing a directory never write or rotate the same file.
+fn shard_file_path(path: &Path, shard_id: u32) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{stem}.shard-{shard_id}.{}", ext.to_string_lossy()),
+        None => format!("{stem}.shard-{shard_id}"),
+    };
+
+    path.with_file_name(name)
+}
+
+/// Shift `path.N` to `path.N+1`, dropping the oldest, and move the active
+/// file to `path.1`.
+async fn rotate(path: &Path) -> std::io::Result<()> {
+    let rotated = |n: usize| {
+        let mut name = path.as_os_str().to_owned();
+        name.push(format!(".{n}"));
+        PathBuf::from(name)
+    };
+
+    let _ = fs::remove_file(rotated(EXPORT_FILE_ROTATIONS)).await;
+    for n in (1..EXPORT_FILE_ROTATIONS).rev() {
+        let _ = fs::rename(rotated(n), rotated(n + 1)).await;
+    }
+
+    fs::rename(path, rotated(1)).await
+}
+
+async fn run_http_sink(url: String, secret: Option<String>, mut rx: mpsc::Receiver<ExportRecord>) {
+    let client = match reqwest::Client::builder().timeout(HTTP_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(e) => {
+            tracing::error!(error = ?e, "failed to build export http client");
+            return;
+        }
+    };
+
+    let mut batch = Vec::with_capacity(HTTP_BATCH_SIZE);
+
+    while let Some(first) = rx.recv().await {
+        batch.push(first);
+
+        let deadline = Instant::now() + HTTP_FLUSH_INTERVAL;
+        while batch.len() < HTTP_BATCH_SIZE {
+            match tokio::time::timeout_at(deadline, rx.recv()).await {
+                Ok(Some(record)) => batch.push(record),
+                Ok(None) | Err(_) => break,
+            }
+        }
+
+        post_batch(&client, &url, secret.as_deref(), &batch).await;
+        batch.clear();
+    }
+}
+
+/// POST a batch, retrying with backoff on network errors, 429s and 5xxs.
+async fn post_batch(
+    client: &reqwest::Client,
+    url: &str,
+    secret: Option<&str>,
+    batch: &[ExportRecord],
+) {
+    let body = match serde_json::to_vec(batch) {
+        Ok(body) => body,
+        Err(e) => {
+            tracing::warn!(error = ?e, "failed to encode export batch");
+            return;
+        }
+    };
+
+    for attempt in 0..HTTP_MAX_ATTEMPTS {
+        if attempt > 0 {
+            tokio::time::sleep(HTTP_RETRY_BASE * 2_u32.pow(attempt - 1)).await;
+        }
+
+        let timestamp = chrono::Utc::now().timestamp_millis().to_string();
+        let mut request = client
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(TIMESTAMP_HEADER, &timestamp)
+            .body(body.clone());
+
+        if let Some(secret) = secret {
+            request = request.header(SIGNATURE_HEADER, sign(secret, &timestamp, &body));
+        }
+
+        match request.send().await {
+            Ok(response) if response.status().is_success() => return,
+            Ok(response)
+                if response.status().is_server_error()
+                    || response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS =>
+            {
+                tracing::debug!(status = %response.status(), attempt, "export endpoint failed, retrying");
+            }
+            Ok(response) => {
+                tracing::warn!(status = %response.status(), count = batch.len(), "export endpoint rejected batch");
+                return;
+            }
+            Err(e) => {
+                tracing::debug!(error = ?e, attempt, "export request failed, retrying");
+            }
+        }
+    }
+
+    tracing::warn!(count = batch.len(), "giving up on export batch");
+}
+
+fn sign(secret: &str, timestamp: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body);
+
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "black-mesa-{name}-{}-{}",
+            std::process::id(),
+            chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn sign_matches_known_vector() {
+        assert_eq!(
+            sign("secret", "1700000000000", br#"[{"event":"test"}]"#),
+            "sha256=1542a9f7474f05c76fd22e7bfb5ff182f00ed2d388ad43dbb2931ef8affa8a7d"
+        );
+    }
+
+    #[test]
+    fn shard_file_path_keeps_extension() {
+        assert_eq!(
+            shard_file_path(Path::new("/var/log/events.jsonl"), 3),
+            PathBuf::from("/var/log/events.shard-3.jsonl")
+        );
+        assert_eq!(
+            shard_file_path(Path::new("events"), 0),
+            PathBuf::from("events.shard-0")
+        );
+    }
+
+    #[tokio::test]
+    async fn rotate_shifts_and_drops_oldest() {
+        let dir = temp_dir("rotate");
+        let path = dir.join("events.jsonl");
+        let rotated = |n: usize| dir.join(format!("events.jsonl.{n}"));
+
+        for i in 0..=EXPORT_FILE_ROTATIONS {
+            std::fs::write(&path, i.to_string()).unwrap();
+            rotate(&path).await.unwrap();
+        }
+
+        assert!(!path.exists());
+        assert!(!rotated(EXPORT_FILE_ROTATIONS + 1).exists());
+        // Newest first, the very first file having been dropped
+        for n in 1..=EXPORT_FILE_ROTATIONS {
+            assert_eq!(
+                std::fs::read_to_string(rotated(n)).unwrap(),
+                (EXPORT_FILE_ROTATIONS + 1 - n).to_string()
+            );
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn file_sink_rotates_on_size() {
+        let dir = temp_dir("sink");
+        let path = dir.join("events.jsonl");
+        let record = ExportRecord {
+            timestamp: 0,
+            guild_id: Id::new(1),
+            event: "test".to_string(),
+            data: HashMap::new(),
+        };
+        let line_len = serde_json::to_vec(&record).unwrap().len() as u64 + 1;
+
+        let mut sink = FileSink::new(path.clone(), line_len * 2);
+        for _ in 0..3 {
+            sink.write(&record).await.unwrap();
+        }
+        sink.close().await.unwrap();
+
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), line_len);
+        assert_eq!(
+            std::fs::metadata(dir.join("events.jsonl.1")).unwrap().len(),
+            line_len * 2
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
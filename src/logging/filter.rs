@@ -0,0 +1,111 @@
+use bm_lib::{
+    discord::Id,
+    model::{logging::LogEvent, Config},
+};
+use tracing::instrument;
+
+use crate::handler::EventHandler;
+
+/// Parents checked for an ignored channel: a thread's channel, then that
+/// channel's category.
+const IGNORE_PARENT_DEPTH: usize = 2;
+
+/// Channel and author of a message log event. The author is unknown for
+/// uncached deletes and bulk deletes.
+fn message_log_subject(event: &LogEvent) -> Option<(Id, Option<Id>)> {
+    match event {
+        LogEvent::MessageDelete {
+            channel_id,
+            user_id,
+            ..
+        } => Some((*channel_id, *user_id)),
+        LogEvent::MessageEdit {
+            channel_id,
+            user_id,
+            ..
+        } => Some((*channel_id, Some(*user_id))),
+        LogEvent::MessageDeleteBulk { channel_id, .. } => Some((*channel_id, None)),
+        _ => None,
+    }
+}
+
+impl EventHandler {
+    /// Whether a message log should be skipped because of the guild's ignore
+    /// lists. Lookups only happen for the lists that are actually in use, and
+    /// a failed lookup never hides a log.
+    #[instrument(skip(self, config, event))]
+    pub(super) async fn is_log_ignored(&self, config: &Config, event: &LogEvent) -> bool {
+        let Some((channel_id, user_id)) = message_log_subject(event) else {
+            return false;
+        };
+        let guild_id = event.guild_id();
+
+        if self
+            .is_channel_log_ignored(config, guild_id, &channel_id)
+            .await
+        {
+            return true;
+        }
+
+        let Some(user_id) = user_id else {
+            return false;
+        };
+
+        if config.logging_ignored_users.contains(&user_id) {
+            return true;
+        }
+
+        if config.logging_ignore_bots {
+            if let Ok(user) = self.get_user(&user_id).await {
+                if user.bot {
+                    return true;
+                }
+            }
+        }
+
+        if !config.logging_ignored_roles.is_empty() {
+            if let Ok(roles) = self.get_member_roles(guild_id, &user_id).await {
+                if roles
+                    .iter()
+                    .any(|role| config.logging_ignored_roles.contains(role))
+                {
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+
+    /// Whether message logs for a channel are ignored, directly or through
+    /// its parent channel or category. Shared with the message cache so the
+    /// two never disagree about a channel.
+    pub(crate) async fn is_channel_log_ignored(
+        &self,
+        config: &Config,
+        guild_id: &Id,
+        channel_id: &Id,
+    ) -> bool {
+        if config.logging_ignored_channels.is_empty() {
+            return false;
+        }
+
+        let mut channel_id = *channel_id;
+        for depth in 0..=IGNORE_PARENT_DEPTH {
+            if config.logging_ignored_channels.contains(&channel_id) {
+                return true;
+            }
+
+            if depth == IGNORE_PARENT_DEPTH {
+                break;
+            }
+
+            match self.get_channel_parent(guild_id, &channel_id).await {
+                Ok(Some(parent_id)) => channel_id = parent_id,
+                _ => break,
+            }
+        }
+
+        false
+    }
+}
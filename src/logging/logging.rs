@@ -6,77 +6,154 @@ use bm_lib::{
 };
 use tracing::instrument;
 
-use crate::{handler::EventHandler, logging::template::render_template};
+use crate::{
+    handler::EventHandler,
+    logging::{
+        batch::{send_now, LogDestination, LogPayload, QueuedLog},
+        export::ExportRecord,
+        template::render_template,
+    },
+};
 
-impl EventHandler {
-    pub(super) async fn send_log_embed(
-        &self,
-        channel_id: &Id,
-        log_config: &LogConfig,
-        vars: &HashMap<String, String>,
-    ) {
-        let title = log_config
-            .embed_title
+/// A file sent along with a log message, e.g. a bulk delete transcript.
+#[derive(Debug, Clone)]
+pub struct LogAttachment {
+    pub filename: String,
+    pub data: Vec<u8>,
+}
+
+/// Render a log config with the given vars into an embed or text message.
+fn render_log_payload(log_config: &LogConfig, vars: &HashMap<String, String>) -> LogPayload {
+    if !log_config.embed {
+        let content = log_config
+            .text_content
             .as_deref()
             .map(|t| render_template(t, vars))
-            .unwrap_or_else(|| log_config.event.clone());
+            .unwrap_or_else(|| format!("[{}] event occurred", log_config.event));
 
-        let body = log_config
-            .embed_body
-            .as_deref()
-            .map(|b| render_template(b, vars))
-            .unwrap_or_default();
+        return LogPayload::Text(content);
+    }
 
-        let color = log_config.embed_color.unwrap_or(0x7289da);
+    let title = log_config
+        .embed_title
+        .as_deref()
+        .map(|t| render_template(t, vars))
+        .unwrap_or_else(|| log_config.event.clone());
 
-        let mut builder = EmbedBuilder::new()
-            .title(title)
-            .description(body)
-            .color(color)
-            .timestamp();
+    let body = log_config
+        .embed_body
+        .as_deref()
+        .map(|b| render_template(b, vars))
+        .unwrap_or_default();
 
-        if let Some(footer) = &log_config.embed_footer {
-            let rendered = render_template(footer, vars);
-            builder = builder.footer(rendered, None);
-        }
+    let color = log_config.embed_color.unwrap_or(0x7289da);
+
+    let mut chars = title.chars().count() + body.chars().count();
 
-        let embed = builder.build();
-        self.rest
-            .create_message_with_embed_and_forget(channel_id, &[embed])
-            .await;
+    let mut builder = EmbedBuilder::new()
+        .title(title)
+        .description(body)
+        .color(color)
+        .timestamp();
+
+    if let Some(footer) = &log_config.embed_footer {
+        let rendered = render_template(footer, vars);
+        chars += rendered.chars().count();
+        builder = builder.footer(rendered, None);
     }
 
-    pub(super) async fn send_log_text(
+    LogPayload::Embed {
+        embed: builder.build(),
+        chars,
+    }
+}
+
+impl EventHandler {
+    fn queue_log(
         &self,
-        channel_id: &Id,
+        destinations: Vec<LogDestination>,
         log_config: &LogConfig,
         vars: &HashMap<String, String>,
+        attachment: Option<LogAttachment>,
     ) {
-        let content = log_config
-            .text_content
-            .as_deref()
-            .map(|t| render_template(t, vars))
-            .unwrap_or_else(|| format!("[{}] event occurred", log_config.event));
+        let payload = render_log_payload(log_config, vars);
+
+        for destination in destinations {
+            self.log_batcher.enqueue(QueuedLog {
+                destination,
+                payload: payload.clone(),
+                attachment: attachment.clone(),
+            });
+        }
+    }
 
-        self.rest
-            .create_message_and_forget(channel_id, &content)
-            .await;
+    /// Render a log config with the given vars into `channel_id`, regardless
+    /// of whether the event is enabled. Used to preview templates, so it is
+    /// sent straight away rather than through the droppable log queue.
+    pub async fn send_log_preview(
+        &self,
+        channel_id: &Id,
+        log_config: &LogConfig,
+        vars: &HashMap<String, String>,
+    ) {
+        send_now(
+            &self.rest,
+            QueuedLog {
+                destination: LogDestination::Channel(*channel_id),
+                payload: render_log_payload(log_config, vars),
+                attachment: None,
+            },
+        )
+        .await;
     }
 
     /// Main entry point: dispatch a typed logging event.
-    /// Checks guild config `logging_enabled`, looks up `LogConfig` for the event,
-    /// renders templates, and sends the log message to the configured channel.
+    /// Checks guild config `logging_enabled` and the ignore lists, looks up
+    /// `LogConfig` for the event, renders templates, and queues the log message
+    /// for the configured and routed channels.
     #[instrument(skip(self, event), fields(guild_id = %event.guild_id(), event = %event.event_type()))]
     pub async fn log_event(&self, event: LogEvent) -> DiscordResult<()> {
+        self.dispatch_log_event(event, None).await
+    }
+
+    /// Dispatch a logging event with a file attached to the log message.
+    #[instrument(skip(self, event, attachment), fields(guild_id = %event.guild_id(), event = %event.event_type()))]
+    pub async fn log_event_with_attachment(
+        &self,
+        event: LogEvent,
+        attachment: LogAttachment,
+    ) -> DiscordResult<()> {
+        self.dispatch_log_event(event, Some(attachment)).await
+    }
+
+    async fn dispatch_log_event(
+        &self,
+        event: LogEvent,
+        attachment: Option<LogAttachment>,
+    ) -> DiscordResult<()> {
         let guild_id = event.guild_id().clone();
         let event_type = event.event_type();
 
+        // Exports see every event, whatever the guild's log settings
+        if self.exporter.enabled() {
+            self.exporter.export(ExportRecord {
+                timestamp: chrono::Utc::now().timestamp_millis(),
+                guild_id,
+                event: event_type.as_db_key().to_string(),
+                data: event.clone().into_vars(),
+            });
+        }
+
         // Check early exits before allocating vars.
         let config = self.get_config(&guild_id).await?;
         if !config.logging_enabled {
             return Ok(());
         }
 
+        if self.is_log_ignored(&config, &event).await {
+            return Ok(());
+        }
+
         let db_key = event_type.as_db_key();
         let log_config = match self.db.get_log_config_for_event(&guild_id, db_key).await {
             Ok(Some(lc)) => lc,
@@ -87,23 +164,38 @@ impl EventHandler {
             }
         };
 
-        let channel_id = log_config
-            .channel_id
-            .or(config.log_channel)
-            .unwrap_or(Id::new(0));
+        if !log_config.enabled {
+            return Ok(());
+        }
 
-        if channel_id.get() == 0 {
+        // An explicit per-event channel wins, then the guild webhook, then
+        // the default log channel. Routed channels always receive a copy.
+        let webhook = config
+            .log_webhook_url
+            .as_deref()
+            .and_then(LogDestination::from_webhook_url);
+        let mut destinations = match (log_config.channel_id, webhook, config.log_channel) {
+            (Some(channel_id), _, _) => vec![LogDestination::Channel(channel_id)],
+            (None, Some(webhook), _) => vec![webhook],
+            (None, None, Some(channel_id)) => vec![LogDestination::Channel(channel_id)],
+            (None, None, None) => Vec::new(),
+        };
+
+        for channel_id in &log_config.routed_channel_ids {
+            let destination = LogDestination::Channel(*channel_id);
+            if !destinations.contains(&destination) {
+                destinations.push(destination);
+            }
+        }
+
+        if destinations.is_empty() {
             return Ok(());
         }
 
         // Only allocate the vars map when we know we'll use it.
         let vars = event.into_vars();
 
-        if log_config.embed {
-            self.send_log_embed(&channel_id, &log_config, &vars).await;
-        } else {
-            self.send_log_text(&channel_id, &log_config, &vars).await;
-        }
+        self.queue_log(destinations, &log_config, &vars, attachment);
 
         Ok(())
     }
@@ -1,2 +1,14 @@
+mod batch;
+mod export;
+mod filter;
 mod logging;
 mod template;
+mod transcript;
+mod voice;
+
+pub use batch::{LogBatcher, LogDestination};
+pub use export::{EventExporter, ExportConfig};
+pub use logging::LogAttachment;
+pub use template::validate_log_config;
+pub use transcript::render_transcript;
+pub use voice::VoiceLogQueue;
@@ -1,50 +1,615 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt};
 
-/// Apply placeholder substitution to a template string.
-/// Replaces `{{key}}` with `vars[key]`.
-/// Single-pass scan: O(template.len()) time, O(1) lookups per placeholder.
-pub fn render_template(template: &str, vars: &HashMap<String, String>) -> String {
-    let mut result = String::with_capacity(template.len());
-    let bytes = template.as_bytes();
-    let mut last_end = 0;
-    let mut i = 0;
-
-    while i < bytes.len() {
-        // Look for opening {{
-        if i + 1 < bytes.len() && bytes[i] == b'{' && bytes[i + 1] == b'{' {
-            // Add text before placeholder
-            result.push_str(&template[last_end..i]);
-
-            // Find closing }}
-            let start = i + 2;
-            let mut j = start;
-            while j + 1 < bytes.len() {
-                if bytes[j] == b'}' && bytes[j + 1] == b'}' {
-                    let key = &template[start..j];
-                    if let Some(value) = vars.get(key) {
-                        result.push_str(value);
-                    } else {
-                        // Key not found, preserve original placeholder
-                        result.push_str(&template[i..j + 2]);
+use bm_lib::{
+    model::{logging::LogEventType, LogConfig},
+    util,
+};
+
+/// Characters Discord treats as markdown, escaped by `escape_markdown`.
+/// Brackets cover masked links, `#` and `-` headers and lists.
+const MARKDOWN_CHARS: &[char] = &['\\', '*', '_', '~', '`', '|', '>', '[', ']', '#', '-'];
+
+/// Error produced while parsing or validating a template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateError {
+    UnknownFilter(String),
+    InvalidArgument { filter: String, argument: String },
+    MissingArgument(String),
+    UnexpectedElse,
+    UnexpectedEndIf,
+    UnclosedIf(String),
+    EmptyTag,
+    UnknownVariable(String),
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::UnknownFilter(name) => write!(f, "unknown filter `{name}`"),
+            TemplateError::InvalidArgument { filter, argument } => {
+                write!(f, "invalid argument `{argument}` for filter `{filter}`")
+            }
+            TemplateError::MissingArgument(filter) => {
+                write!(f, "filter `{filter}` needs an argument")
+            }
+            TemplateError::UnexpectedElse => write!(f, "`{{{{else}}}}` outside of `{{{{#if}}}}`"),
+            TemplateError::UnexpectedEndIf => write!(f, "`{{{{/if}}}}` without `{{{{#if}}}}`"),
+            TemplateError::UnclosedIf(name) => write!(f, "`{{{{#if {name}}}}}` is never closed"),
+            TemplateError::EmptyTag => write!(f, "empty `{{{{}}}}` tag"),
+            TemplateError::UnknownVariable(name) => {
+                write!(f, "unknown variable `{name}` for this event")
+            }
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Filter {
+    /// Keep at most this many characters, marking cut values with `...`.
+    Truncate(usize),
+    Upper,
+    Lower,
+    /// Unix seconds as a Discord timestamp with the given style.
+    Timestamp(String),
+    /// Seconds as a human readable duration.
+    Duration,
+    /// User ID as a mention.
+    Mention,
+    /// Channel ID as a channel link.
+    Channel,
+    EscapeMarkdown,
+    /// Used when the variable is missing or empty.
+    Default(String),
+}
+
+impl Filter {
+    fn parse(spec: &str) -> Result<Self, TemplateError> {
+        let (name, argument) = match spec.split_once(':') {
+            Some((name, argument)) => (name.trim(), Some(unquote(argument.trim()))),
+            None => (spec.trim(), None),
+        };
+
+        let invalid = |argument: &str| TemplateError::InvalidArgument {
+            filter: name.to_string(),
+            argument: argument.to_string(),
+        };
+
+        match (name, argument) {
+            ("truncate", Some(argument)) => argument
+                .parse()
+                .map(Filter::Truncate)
+                .map_err(|_| invalid(argument)),
+            ("truncate", None) | ("default", None) => {
+                Err(TemplateError::MissingArgument(name.to_string()))
+            }
+            ("timestamp", Some(style)) => match style {
+                "t" | "T" | "d" | "D" | "f" | "F" | "R" => Ok(Filter::Timestamp(style.to_string())),
+                _ => Err(invalid(style)),
+            },
+            ("timestamp", None) => Ok(Filter::Timestamp("f".to_string())),
+            ("default", Some(argument)) => Ok(Filter::Default(argument.to_string())),
+            ("upper", None) => Ok(Filter::Upper),
+            ("lower", None) => Ok(Filter::Lower),
+            ("duration", None) => Ok(Filter::Duration),
+            ("mention", None) => Ok(Filter::Mention),
+            ("channel", None) => Ok(Filter::Channel),
+            ("escape_markdown", None) => Ok(Filter::EscapeMarkdown),
+            (
+                "upper" | "lower" | "duration" | "mention" | "channel" | "escape_markdown",
+                Some(argument),
+            ) => Err(invalid(argument)),
+            _ => Err(TemplateError::UnknownFilter(name.to_string())),
+        }
+    }
+
+    fn apply(&self, value: String) -> String {
+        match self {
+            Filter::Truncate(max) => {
+                if value.chars().count() <= *max {
+                    value
+                } else {
+                    let cut: String = value.chars().take(*max).collect();
+                    format!("{cut}...")
+                }
+            }
+            Filter::Upper => value.to_uppercase(),
+            Filter::Lower => value.to_lowercase(),
+            Filter::Timestamp(style) => match value.parse::<i64>() {
+                Ok(seconds) => format!("<t:{seconds}:{style}>"),
+                Err(_) => value,
+            },
+            Filter::Duration => match value.parse::<u64>() {
+                Ok(seconds) => util::format_duration(seconds),
+                Err(_) => value,
+            },
+            Filter::Mention => match value.parse::<u64>() {
+                Ok(id) => format!("<@{id}>"),
+                Err(_) => value,
+            },
+            Filter::Channel => match value.parse::<u64>() {
+                Ok(id) => format!("<#{id}>"),
+                Err(_) => value,
+            },
+            Filter::EscapeMarkdown => {
+                let mut escaped = String::with_capacity(value.len());
+                for c in value.chars() {
+                    if MARKDOWN_CHARS.contains(&c) {
+                        escaped.push('\\');
                     }
-                    i = j + 2;
-                    last_end = i;
-                    // Move past this placeholder and continue scanning
-                    break;
+                    escaped.push(c);
+                }
+                escaped
+            }
+            Filter::Default(default) => {
+                if value.is_empty() {
+                    default.clone()
+                } else {
+                    value
                 }
-                j += 1;
             }
+        }
+    }
+}
+
+/// Strip one pair of matching quotes, so `default:"no reason"` can hold spaces.
+fn unquote(argument: &str) -> &str {
+    argument
+        .strip_prefix('"')
+        .and_then(|a| a.strip_suffix('"'))
+        .unwrap_or(argument)
+}
+
+/// Split a tag on `|`, ignoring pipes inside quotes.
+fn split_pipes(tag: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut in_quotes = false;
+    let mut start = 0;
 
-            // no closing found, add rest of string and exit
-            if j + 1 >= bytes.len() {
-                result.push_str(&template[i..]);
-                return result;
+    for (i, c) in tag.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '|' if !in_quotes => {
+                parts.push(&tag[start..i]);
+                start = i + 1;
             }
+            _ => {}
+        }
+    }
+
+    parts.push(&tag[start..]);
+    parts
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Text(String),
+    Variable {
+        name: String,
+        filters: Vec<Filter>,
+        /// The tag as written, kept when the variable is missing.
+        raw: String,
+    },
+    If {
+        name: String,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+}
+
+/// A parsed log template.
+///
+/// Supported syntax:
+/// - `{{name}}` inserts a variable; missing variables are left as written
+/// - `{{name | filter | filter:arg}}` applies filters left to right:
+///   `truncate:N`, `upper`, `lower`, `timestamp[:style]`, `duration`,
+///   `mention`, `channel`, `escape_markdown` and `default:"text"`
+/// - `{{#if name}}...{{else}}...{{/if}}` renders the first branch when the
+///   variable is present and not empty, and may be nested
+#[derive(Debug, Clone)]
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+/// A template split into literal text and `{{...}}` tags.
+enum Token<'a> {
+    Text(&'a str),
+    /// Trimmed tag content and the tag as written.
+    Tag(&'a str, &'a str),
+}
+
+fn tokenize(source: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut rest = source;
+
+    while let Some(open) = rest.find("{{") {
+        let Some(close) = rest[open + 2..].find("}}") else {
+            // No closing braces, the rest is plain text
+            break;
+        };
+        let end = open + 2 + close + 2;
+
+        if open > 0 {
+            tokens.push(Token::Text(&rest[..open]));
+        }
+        tokens.push(Token::Tag(rest[open + 2..end - 2].trim(), &rest[open..end]));
+        rest = &rest[end..];
+    }
+
+    if !rest.is_empty() {
+        tokens.push(Token::Text(rest));
+    }
+
+    tokens
+}
+
+/// What stopped [`parse_nodes`].
+enum End {
+    Else,
+    EndIf,
+    Eof,
+}
+
+fn parse_nodes<'a>(
+    tokens: &mut impl Iterator<Item = Token<'a>>,
+) -> Result<(Vec<Node>, End), TemplateError> {
+    let mut nodes = Vec::new();
+
+    while let Some(token) = tokens.next() {
+        let (tag, raw) = match token {
+            Token::Text(text) => {
+                nodes.push(Node::Text(text.to_string()));
+                continue;
+            }
+            Token::Tag(tag, raw) => (tag, raw),
+        };
+
+        // `{{#iffoo}}` is a variable, not a condition on `foo`
+        if let Some(name) = tag
+            .strip_prefix("#if")
+            .filter(|name| name.is_empty() || name.starts_with(char::is_whitespace))
+        {
+            let name = name.trim();
+            if name.is_empty() {
+                return Err(TemplateError::EmptyTag);
+            }
+
+            let (then, end) = parse_nodes(tokens)?;
+            let otherwise = match end {
+                End::EndIf => Vec::new(),
+                End::Else => match parse_nodes(tokens)? {
+                    (otherwise, End::EndIf) => otherwise,
+                    (_, End::Else) => return Err(TemplateError::UnexpectedElse),
+                    (_, End::Eof) => return Err(TemplateError::UnclosedIf(name.to_string())),
+                },
+                End::Eof => return Err(TemplateError::UnclosedIf(name.to_string())),
+            };
+
+            nodes.push(Node::If {
+                name: name.to_string(),
+                then,
+                otherwise,
+            });
+        } else if tag == "else" {
+            return Ok((nodes, End::Else));
+        } else if tag == "/if" {
+            return Ok((nodes, End::EndIf));
         } else {
-            i += 1;
+            let mut parts = split_pipes(tag).into_iter();
+            let name = parts.next().unwrap_or_default().trim();
+            if name.is_empty() {
+                return Err(TemplateError::EmptyTag);
+            }
+
+            nodes.push(Node::Variable {
+                name: name.to_string(),
+                filters: parts.map(Filter::parse).collect::<Result<_, _>>()?,
+                raw: raw.to_string(),
+            });
         }
     }
 
-    result.push_str(&template[last_end..]);
-    result
+    Ok((nodes, End::Eof))
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Self, TemplateError> {
+        match parse_nodes(&mut tokenize(source).into_iter())? {
+            (nodes, End::Eof) => Ok(Self { nodes }),
+            (_, End::Else) => Err(TemplateError::UnexpectedElse),
+            (_, End::EndIf) => Err(TemplateError::UnexpectedEndIf),
+        }
+    }
+
+    pub fn render(&self, vars: &HashMap<String, String>) -> String {
+        let mut out = String::new();
+        render_nodes(&self.nodes, vars, &mut out);
+        out
+    }
+
+    /// Every variable the template refers to, including `{{#if}}` conditions.
+    pub fn variables(&self) -> Vec<&str> {
+        let mut names = Vec::new();
+        collect_variables(&self.nodes, &mut names);
+        names
+    }
+
+    /// Reject variables that are not in `allowed`.
+    pub fn validate(&self, allowed: &[&str]) -> Result<(), TemplateError> {
+        match self
+            .variables()
+            .into_iter()
+            .find(|name| !allowed.contains(name))
+        {
+            Some(name) => Err(TemplateError::UnknownVariable(name.to_string())),
+            None => Ok(()),
+        }
+    }
+}
+
+fn render_nodes(nodes: &[Node], vars: &HashMap<String, String>, out: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Variable { name, filters, raw } => {
+                let has_default = filters.iter().any(|f| matches!(f, Filter::Default(_)));
+                match vars.get(name) {
+                    Some(value) => {
+                        let value = filters
+                            .iter()
+                            .fold(value.clone(), |value, filter| filter.apply(value));
+                        out.push_str(&value);
+                    }
+                    // Missing with a default: run the chain on an empty value
+                    None if has_default => {
+                        let value = filters
+                            .iter()
+                            .fold(String::new(), |value, filter| filter.apply(value));
+                        out.push_str(&value);
+                    }
+                    // Missing, preserve the original placeholder
+                    None => out.push_str(raw),
+                }
+            }
+            Node::If {
+                name,
+                then,
+                otherwise,
+            } => {
+                let truthy = vars.get(name).is_some_and(|value| !value.is_empty());
+                render_nodes(if truthy { then } else { otherwise }, vars, out);
+            }
+        }
+    }
+}
+
+fn collect_variables<'a>(nodes: &'a [Node], names: &mut Vec<&'a str>) {
+    for node in nodes {
+        match node {
+            Node::Text(_) => {}
+            Node::Variable { name, .. } => names.push(name),
+            Node::If {
+                name,
+                then,
+                otherwise,
+            } => {
+                names.push(name);
+                collect_variables(then, names);
+                collect_variables(otherwise, names);
+            }
+        }
+    }
+}
+
+/// Render a template string against event variables.
+///
+/// Templates are validated when saved, so a parse error here means the
+/// stored template predates validation; it is sent unrendered rather than
+/// dropping the log.
+pub fn render_template(template: &str, vars: &HashMap<String, String>) -> String {
+    match Template::parse(template) {
+        Ok(parsed) => parsed.render(vars),
+        Err(e) => {
+            tracing::warn!(error = %e, "invalid log template");
+            template.to_string()
+        }
+    }
+}
+
+/// Check every template of a log config against the variables its event
+/// provides. Run before a config is saved so broken templates never reach
+/// [`render_template`].
+pub fn validate_log_config(log_config: &LogConfig) -> Result<(), TemplateError> {
+    let allowed = LogEventType::from_db_key(&log_config.event)
+        .map(|event_type| event_type.variables())
+        .unwrap_or_default();
+
+    [
+        &log_config.embed_title,
+        &log_config.embed_body,
+        &log_config.embed_footer,
+        &log_config.text_content,
+    ]
+    .into_iter()
+    .flatten()
+    .try_for_each(|template| Template::parse(template)?.validate(allowed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn render(source: &str, pairs: &[(&str, &str)]) -> String {
+        Template::parse(source).unwrap().render(&vars(pairs))
+    }
+
+    #[test]
+    fn renders_variables_and_text() {
+        assert_eq!(
+            render(
+                "{{user}} joined {{guild}}",
+                &[("user", "a"), ("guild", "b")]
+            ),
+            "a joined b"
+        );
+        assert_eq!(render("no tags", &[]), "no tags");
+        assert_eq!(render("{{ user }}", &[("user", "a")]), "a");
+    }
+
+    #[test]
+    fn keeps_missing_variables_as_written() {
+        assert_eq!(
+            render("hi {{ user | upper }}", &[]),
+            "hi {{ user | upper }}"
+        );
+    }
+
+    #[test]
+    fn unclosed_braces_are_text() {
+        assert_eq!(render("{{user}} {{oops", &[("user", "a")]), "a {{oops");
+    }
+
+    #[test]
+    fn if_else_branches() {
+        let source = "{{#if reason}}because {{reason}}{{else}}no reason{{/if}}";
+        assert_eq!(render(source, &[("reason", "spam")]), "because spam");
+        assert_eq!(render(source, &[("reason", "")]), "no reason");
+        assert_eq!(render(source, &[]), "no reason");
+    }
+
+    #[test]
+    fn nested_if() {
+        let source = "{{#if a}}A{{#if b}}B{{/if}}{{else}}-{{/if}}";
+        assert_eq!(render(source, &[("a", "1"), ("b", "1")]), "AB");
+        assert_eq!(render(source, &[("a", "1")]), "A");
+        assert_eq!(render(source, &[("b", "1")]), "-");
+    }
+
+    #[test]
+    fn if_requires_whitespace_before_name() {
+        let template = Template::parse("{{#iffoo}}").unwrap();
+        assert_eq!(template.variables(), vec!["#iffoo"]);
+        assert_eq!(
+            template.validate(&["foo"]),
+            Err(TemplateError::UnknownVariable("#iffoo".to_string()))
+        );
+        assert_eq!(
+            Template::parse("{{#if}}x{{/if}}").unwrap_err(),
+            TemplateError::EmptyTag
+        );
+    }
+
+    #[test]
+    fn structure_errors() {
+        assert_eq!(
+            Template::parse("{{else}}").unwrap_err(),
+            TemplateError::UnexpectedElse
+        );
+        assert_eq!(
+            Template::parse("{{/if}}").unwrap_err(),
+            TemplateError::UnexpectedEndIf
+        );
+        assert_eq!(
+            Template::parse("{{#if a}}x").unwrap_err(),
+            TemplateError::UnclosedIf("a".to_string())
+        );
+        assert_eq!(
+            Template::parse("{{#if a}}x{{else}}y{{else}}z{{/if}}").unwrap_err(),
+            TemplateError::UnexpectedElse
+        );
+        assert_eq!(
+            Template::parse("{{ }}").unwrap_err(),
+            TemplateError::EmptyTag
+        );
+    }
+
+    #[test]
+    fn filters() {
+        assert_eq!(render("{{v | truncate:3}}", &[("v", "abcdef")]), "abc...");
+        assert_eq!(render("{{v | truncate:10}}", &[("v", "abc")]), "abc");
+        assert_eq!(render("{{v | upper}}", &[("v", "abc")]), "ABC");
+        assert_eq!(render("{{v | lower}}", &[("v", "ABC")]), "abc");
+        assert_eq!(render("{{v | timestamp}}", &[("v", "10")]), "<t:10:f>");
+        assert_eq!(render("{{v | timestamp:R}}", &[("v", "10")]), "<t:10:R>");
+        assert_eq!(render("{{v | mention}}", &[("v", "1")]), "<@1>");
+        assert_eq!(render("{{v | channel}}", &[("v", "1")]), "<#1>");
+        assert_eq!(render("{{v | mention}}", &[("v", "x")]), "x");
+        assert_eq!(
+            render("{{v | lower | truncate:2}}", &[("v", "ABC")]),
+            "ab..."
+        );
+    }
+
+    #[test]
+    fn default_filter() {
+        let source = r#"{{reason | default:"no | reason"}}"#;
+        assert_eq!(render(source, &[]), "no | reason");
+        assert_eq!(render(source, &[("reason", "")]), "no | reason");
+        assert_eq!(render(source, &[("reason", "spam")]), "spam");
+    }
+
+    #[test]
+    fn escape_markdown() {
+        assert_eq!(
+            render("{{v | escape_markdown}}", &[("v", "*a* [b](c) # - >")]),
+            r"\*a\* \[b\](c) \# \- \>"
+        );
+    }
+
+    #[test]
+    fn filter_errors() {
+        assert_eq!(
+            Template::parse("{{v | nope}}").unwrap_err(),
+            TemplateError::UnknownFilter("nope".to_string())
+        );
+        assert_eq!(
+            Template::parse("{{v | truncate}}").unwrap_err(),
+            TemplateError::MissingArgument("truncate".to_string())
+        );
+        assert_eq!(
+            Template::parse("{{v | truncate:x}}").unwrap_err(),
+            TemplateError::InvalidArgument {
+                filter: "truncate".to_string(),
+                argument: "x".to_string(),
+            }
+        );
+        assert_eq!(
+            Template::parse("{{v | timestamp:x}}").unwrap_err(),
+            TemplateError::InvalidArgument {
+                filter: "timestamp".to_string(),
+                argument: "x".to_string(),
+            }
+        );
+        assert_eq!(
+            Template::parse("{{v | upper:x}}").unwrap_err(),
+            TemplateError::InvalidArgument {
+                filter: "upper".to_string(),
+                argument: "x".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn validate_checks_every_variable() {
+        let template = Template::parse("{{#if a}}{{b}}{{else}}{{c | upper}}{{/if}}").unwrap();
+        assert_eq!(template.variables(), vec!["a", "b", "c"]);
+        assert_eq!(template.validate(&["a", "b", "c"]), Ok(()));
+        assert_eq!(
+            template.validate(&["a", "b"]),
+            Err(TemplateError::UnknownVariable("c".to_string()))
+        );
+    }
+
+    #[test]
+    fn render_template_falls_back_to_source() {
+        assert_eq!(render_template("{{#if a}}", &HashMap::new()), "{{#if a}}");
+    }
 }
@@ -0,0 +1,45 @@
+use std::fmt::Write;
+
+use bm_lib::discord::Id;
+
+use crate::handler::message_cache::CachedMessage;
+
+/// Render deleted messages as a plain text transcript, oldest first.
+///
+/// `total` is the number of messages deleted; any that were not cached are
+/// counted in the header but have no content to show.
+pub fn render_transcript(channel_id: &Id, messages: &mut [CachedMessage], total: usize) -> String {
+    messages.sort_by_key(|message| message.created_at);
+
+    let mut transcript = format!(
+        "Bulk delete in channel {channel_id}: {total} messages, {} cached\n\n",
+        messages.len()
+    );
+
+    for message in messages.iter() {
+        let timestamp = chrono::DateTime::from_timestamp_millis(message.created_at as i64)
+            .map(|time| time.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+            .unwrap_or_default();
+
+        let _ = writeln!(
+            transcript,
+            "[{timestamp}] {} ({}): {}",
+            message.author_name, message.author_id, message.content
+        );
+
+        if !message.attachments.is_empty() {
+            let _ = writeln!(
+                transcript,
+                "    attachments: {}",
+                message.attachments.join(", ")
+            );
+        }
+    }
+
+    let missing = total.saturating_sub(messages.len());
+    if missing > 0 {
+        let _ = writeln!(transcript, "\n{missing} messages were not cached");
+    }
+
+    transcript
+}
@@ -0,0 +1,346 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex as StdMutex},
+    time::Duration,
+};
+
+use bm_lib::{
+    discord::{DiscordError, DiscordResult, Guild, Id, VoiceStateUpdate},
+    model::logging::LogEvent,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tracing::instrument;
+
+use crate::handler::EventHandler;
+
+/// How long a guild's voice activity is kept after its last change. Members
+/// still in voice are reseeded from the next guild create.
+const VOICE_ACTIVITY_TTL: Duration = Duration::from_secs(60 * 60 * 24);
+
+/// Voice state updates held for logging before new ones are dropped.
+const VOICE_LOG_QUEUE_CAPACITY: usize = 1024;
+
+/// `KEYS[1]` is the guild's activity hash, `ARGV[1]` the member.
+const GET_ACTIVITY_SCRIPT: &str = r#"
+return redis.call('HGET', KEYS[1], ARGV[1])
+"#;
+
+/// Store or, given an empty value, remove a member's activity and refresh
+/// the hash's TTL.
+///
+/// `KEYS[1]` is the guild's activity hash. `ARGV[1]` is the member,
+/// `ARGV[2]` the serialized activity, `ARGV[3]` the TTL in ms.
+const SET_ACTIVITY_SCRIPT: &str = r#"
+if ARGV[2] == '' then
+    redis.call('HDEL', KEYS[1], ARGV[1])
+else
+    redis.call('HSET', KEYS[1], ARGV[1], ARGV[2])
+end
+redis.call('PEXPIRE', KEYS[1], ARGV[3])
+return 1
+"#;
+
+/// Whole hash as a flat `[member, activity, ...]` list.
+///
+/// `KEYS[1]` is the guild's activity hash.
+const READ_ACTIVITY_SCRIPT: &str = r#"
+return redis.call('HGETALL', KEYS[1])
+"#;
+
+/// Replace the guild's activity.
+///
+/// `KEYS[1]` is the guild's activity hash. `ARGV[1]` is the TTL in ms,
+/// followed by `member, activity` pairs.
+const SEED_ACTIVITY_SCRIPT: &str = r#"
+redis.call('DEL', KEYS[1])
+for i = 2, #ARGV, 2 do
+    redis.call('HSET', KEYS[1], ARGV[i], ARGV[i + 1])
+end
+redis.call('PEXPIRE', KEYS[1], ARGV[1])
+return 1
+"#;
+
+#[inline]
+fn voice_activity_key(guild_id: &Id) -> String {
+    format!("voice_activity:{}", guild_id)
+}
+
+/// What we last saw of a member in voice, kept to log what changed and for
+/// how long.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct VoiceActivity {
+    channel_id: Id,
+    /// Unix seconds when the member joined `channel_id`.
+    joined_at: i64,
+    mute: bool,
+    deaf: bool,
+    /// Unix seconds when the member started streaming, if they are.
+    streaming_since: Option<i64>,
+}
+
+impl VoiceActivity {
+    /// Keep join and stream times from the previous activity while the member
+    /// stays in the same channel.
+    fn carry_over(mut self, previous: Option<&VoiceActivity>) -> Self {
+        if let Some(previous) = previous.filter(|p| p.channel_id == self.channel_id) {
+            self.joined_at = previous.joined_at;
+            if self.streaming_since.is_some() {
+                self.streaming_since = previous.streaming_since.or(self.streaming_since);
+            }
+        }
+        self
+    }
+}
+
+fn encode(activity: &VoiceActivity) -> DiscordResult<String> {
+    serde_json::to_string(activity)
+        .map_err(|e| DiscordError::ParseError(format!("Failed to encode voice activity: {e}")))
+}
+
+/// Voice state updates waiting to be logged. Updates are logged by a single
+/// task in the order they arrived, off the voice event path.
+pub struct VoiceLogQueue {
+    tx: mpsc::Sender<(Id, VoiceStateUpdate)>,
+    rx: StdMutex<Option<mpsc::Receiver<(Id, VoiceStateUpdate)>>>,
+}
+
+impl Default for VoiceLogQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VoiceLogQueue {
+    pub fn new() -> Self {
+        let (tx, rx) = mpsc::channel(VOICE_LOG_QUEUE_CAPACITY);
+
+        Self {
+            tx,
+            rx: StdMutex::new(Some(rx)),
+        }
+    }
+
+    pub fn enqueue(&self, guild_id: Id, vs: &VoiceStateUpdate) {
+        if self.tx.try_send((guild_id, vs.clone())).is_err() {
+            tracing::warn!(guild_id = %guild_id, "voice log queue full, dropping update");
+        }
+    }
+}
+
+impl EventHandler {
+    /// Log joins, leaves, moves, server mutes/deafens and streams by diffing
+    /// against the last voice state seen for the member.
+    #[instrument(skip(self, vs), fields(user_id = %vs.user_id))]
+    pub async fn log_voice_state(&self, guild_id: Id, vs: &VoiceStateUpdate) -> DiscordResult<()> {
+        let now = chrono::Utc::now().timestamp();
+        let user_id = vs.user_id;
+        let username = vs.member.as_ref().map(|m| m.user.username.to_string());
+        let streaming = vs.self_stream.unwrap_or(false);
+
+        let current = vs.channel_id.map(|channel_id| VoiceActivity {
+            channel_id,
+            joined_at: now,
+            mute: vs.mute,
+            deaf: vs.deaf,
+            streaming_since: streaming.then_some(now),
+        });
+
+        let previous = self
+            .swap_voice_activity(&guild_id, &user_id, current.as_ref())
+            .await?;
+
+        let previous_channel = previous.as_ref().map(|p| p.channel_id);
+        let current_channel = current.as_ref().map(|c| c.channel_id);
+        let changed_channel = previous_channel != current_channel;
+
+        let mut events = Vec::new();
+
+        // A stream ends when the member stops it or leaves the channel
+        if let Some(previous) = &previous {
+            if let Some(since) = previous.streaming_since {
+                if changed_channel || !streaming {
+                    events.push(LogEvent::VoiceStreamStop {
+                        guild_id,
+                        user_id,
+                        username: username.clone(),
+                        channel_id: previous.channel_id,
+                        duration_secs: (now - since).max(0) as u64,
+                    });
+                }
+            }
+        }
+
+        match (&previous, &current) {
+            (None, Some(current)) => events.push(LogEvent::VoiceJoin {
+                guild_id,
+                user_id,
+                username: username.clone(),
+                channel_id: current.channel_id,
+            }),
+            (Some(previous), None) => events.push(LogEvent::VoiceLeave {
+                guild_id,
+                user_id,
+                username: username.clone(),
+                channel_id: previous.channel_id,
+                duration_secs: (now - previous.joined_at).max(0) as u64,
+            }),
+            (Some(previous), Some(current)) => {
+                if changed_channel {
+                    events.push(LogEvent::VoiceMove {
+                        guild_id,
+                        user_id,
+                        username: username.clone(),
+                        from_channel_id: previous.channel_id,
+                        to_channel_id: current.channel_id,
+                        duration_secs: (now - previous.joined_at).max(0) as u64,
+                    });
+                }
+
+                if previous.mute != current.mute {
+                    events.push(LogEvent::VoiceServerMute {
+                        guild_id,
+                        user_id,
+                        username: username.clone(),
+                        channel_id: current.channel_id,
+                        muted: current.mute,
+                    });
+                }
+
+                if previous.deaf != current.deaf {
+                    events.push(LogEvent::VoiceServerDeafen {
+                        guild_id,
+                        user_id,
+                        username: username.clone(),
+                        channel_id: current.channel_id,
+                        deafened: current.deaf,
+                    });
+                }
+            }
+            (None, None) => {}
+        }
+
+        let was_streaming = previous
+            .as_ref()
+            .is_some_and(|p| p.streaming_since.is_some());
+        if let Some(current) = &current {
+            if streaming && (changed_channel || !was_streaming) {
+                events.push(LogEvent::VoiceStreamStart {
+                    guild_id,
+                    user_id,
+                    username,
+                    channel_id: current.channel_id,
+                });
+            }
+        }
+
+        for event in events {
+            let _ = self.log_event(event).await;
+        }
+
+        Ok(())
+    }
+
+    /// Replace the stored voice activity and return the previous one. Join
+    /// and stream times are kept while the member stays in the same channel.
+    async fn swap_voice_activity(
+        &self,
+        guild_id: &Id,
+        user_id: &Id,
+        current: Option<&VoiceActivity>,
+    ) -> DiscordResult<Option<VoiceActivity>> {
+        let key = voice_activity_key(guild_id);
+        let previous = self
+            .cache
+            .eval::<Option<String>>(GET_ACTIVITY_SCRIPT, &[&key], &[user_id.to_string()])
+            .await?
+            .and_then(|value| serde_json::from_str::<VoiceActivity>(&value).ok());
+
+        let value = match current {
+            Some(current) => encode(&current.clone().carry_over(previous.as_ref()))?,
+            None => String::new(),
+        };
+
+        self.cache
+            .eval::<u64>(
+                SET_ACTIVITY_SCRIPT,
+                &[&key],
+                &[
+                    user_id.to_string(),
+                    value,
+                    VOICE_ACTIVITY_TTL.as_millis().to_string(),
+                ],
+            )
+            .await?;
+
+        Ok(previous)
+    }
+
+    /// Reset the guild's voice activity to the members in voice on guild
+    /// create, dropping anyone whose leave was missed while disconnected.
+    #[instrument(skip(self, guild), fields(guild_id = %guild.id))]
+    pub async fn seed_voice_activity(&self, guild: &Guild) -> DiscordResult<()> {
+        let key = voice_activity_key(&guild.id);
+        let now = chrono::Utc::now().timestamp();
+
+        let flat = self
+            .cache
+            .eval::<Vec<String>>(READ_ACTIVITY_SCRIPT, &[&key], &[])
+            .await?;
+        let previous: HashMap<&str, VoiceActivity> = flat
+            .chunks_exact(2)
+            .filter_map(|pair| Some((pair[0].as_str(), serde_json::from_str(&pair[1]).ok()?)))
+            .collect();
+
+        let mut args = vec![VOICE_ACTIVITY_TTL.as_millis().to_string()];
+        for vs in &guild.voice_states {
+            let Some(channel_id) = vs.channel_id else {
+                continue;
+            };
+
+            let user_id = vs.user_id.to_string();
+            let activity = VoiceActivity {
+                channel_id,
+                joined_at: now,
+                mute: vs.mute,
+                deaf: vs.deaf,
+                streaming_since: vs.self_stream.unwrap_or(false).then_some(now),
+            }
+            .carry_over(previous.get(user_id.as_str()));
+
+            args.push(user_id);
+            args.push(encode(&activity)?);
+        }
+
+        self.cache
+            .eval::<u64>(SEED_ACTIVITY_SCRIPT, &[&key], &args)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Start logging queued voice state updates. Must be called once at
+    /// startup.
+    pub fn spawn_voice_logger(self: &Arc<Self>) {
+        let rx = self
+            .voice_log
+            .rx
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take();
+
+        let Some(mut rx) = rx else {
+            tracing::warn!("voice logger already started");
+            return;
+        };
+
+        let handler = Arc::clone(self);
+        tokio::spawn(async move {
+            while let Some((guild_id, vs)) = rx.recv().await {
+                if let Err(e) = handler.log_voice_state(guild_id, &vs).await {
+                    tracing::warn!(error = ?e, "Failed to log voice state");
+                }
+            }
+        });
+    }
+}
@@ -6,8 +6,9 @@ mod logging;
 mod telemetry;
 mod workers;
 
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
+use automod::PhishingDatabase;
 use bm_lib::{
     cache::{Cache, RedisCache},
     clients::{MesastreamClient, MesastreamWsClient},
@@ -16,12 +17,15 @@ use bm_lib::{
 };
 use config::Config;
 use handler::EventHandler;
+use logging::{EventExporter, ExportConfig};
 
 const SERVICE_NAME: &str = concat!(env!("CARGO_PKG_NAME"), " v", env!("CARGO_PKG_VERSION"));
 
 const AUTHOR_COLON_THREE: &str = "@dhopcs"; // hi :3
 const GOAT_ID: Id = Id::new(206309860038410240); // hi :3
 
+const PHISHING_RELOAD_INTERVAL: Duration = Duration::from_secs(60);
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     dotenv::dotenv().ok();
@@ -44,7 +48,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     db.migrate().await?;
 
-    let worker = workers::Worker::new(20, Arc::clone(&db), Arc::clone(&rest));
+    let worker = workers::Worker::new(20, Arc::clone(&db), Arc::clone(&rest), Arc::clone(&cache));
     tokio::spawn(async move {
         worker.start_expiry().await;
     });
@@ -54,11 +58,27 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         config.mesastream_token.clone(),
     ));
 
+    let phishing = Arc::new(PhishingDatabase::new(config.phishing_domains_path.clone()));
+    if let Err(e) = phishing.reload_if_changed().await {
+        tracing::warn!(error = %e, "failed to load phishing domain list");
+    }
+    tokio::spawn(Arc::clone(&phishing).watch(PHISHING_RELOAD_INTERVAL));
+
+    let exporter = Arc::new(EventExporter::spawn(ExportConfig {
+        shard_id: config.shard_id,
+        file_path: config.export_file_path.clone(),
+        file_max_bytes: config.export_file_max_bytes,
+        http_url: config.export_http_url.clone(),
+        http_secret: config.export_http_secret.clone(),
+    }));
+
     let event_handler = Arc::new(EventHandler::new(
         rest.clone(),
         cache.clone(),
         db.clone(),
         mesastream.clone(),
+        phishing,
+        exporter,
     ));
 
     // Spawn the mesastream WebSocket event listener.
@@ -71,6 +91,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let (ws_client, ws_rx) = MesastreamWsClient::new(ws_url);
     tokio::spawn(async move { ws_client.run().await });
     event_handler.spawn_mesastream_event_handler(ws_rx);
+    event_handler.spawn_raid_watcher();
+    event_handler.spawn_log_batcher();
+    event_handler.spawn_voice_logger();
 
     let shard_config = ShardConfig::new(config.shard_id, config.num_shards);
 
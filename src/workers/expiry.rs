@@ -1,12 +1,14 @@
 use std::{borrow::Cow, sync::Arc};
 
 use bm_lib::{
+    cache::{Cache, RedisCache},
     db::Database,
-    discord::{DiscordRestClient, DiscordResult},
-    model::InfractionType,
+    discord::{DiscordRestClient, DiscordResult, Id},
+    model::{Infraction, InfractionType},
 };
 
 use super::Worker;
+use crate::handler::{data::roles_cache_key, moderation::restored_roles};
 
 const REASON: &str = "Infraction expired";
 
@@ -14,7 +16,13 @@ impl Worker {
     pub async fn start_expiry(&self) {
         tracing::info!("Starting expiry worker");
         loop {
-            if let Err(e) = expiry_job(Arc::clone(&self.rest), Arc::clone(&self.db)).await {
+            if let Err(e) = expiry_job(
+                Arc::clone(&self.rest),
+                Arc::clone(&self.db),
+                Arc::clone(&self.cache),
+            )
+            .await
+            {
                 tracing::error!("Error in expiry job: {:?}", e);
             }
             tokio::time::sleep(std::time::Duration::from_secs(self.interval)).await;
@@ -22,7 +30,11 @@ impl Worker {
     }
 }
 
-async fn expiry_job(rest: Arc<DiscordRestClient>, db: Arc<Database>) -> DiscordResult<()> {
+async fn expiry_job(
+    rest: Arc<DiscordRestClient>,
+    db: Arc<Database>,
+    cache: Arc<Cache<RedisCache>>,
+) -> DiscordResult<()> {
     let infractions = db.get_expired_infractions().await?;
 
     for infraction in infractions {
@@ -50,6 +62,36 @@ async fn expiry_job(rest: Arc<DiscordRestClient>, db: Arc<Database>) -> DiscordR
             }
         }
 
+        if let (InfractionType::Quarantine, Some(stored)) =
+            (&infraction.infraction_type, &infraction.stored_roles)
+        {
+            match restore_roles(&rest, &infraction, stored).await {
+                Err(e) => tracing::warn!(
+                    infraction_id = %infraction.uuid,
+                    error = ?e,
+                    "modify_member_roles failed, deactivating infraction anyway"
+                ),
+                Ok(roles) => {
+                    // Keep the cached roles in step, later quarantines and
+                    // permission checks read them
+                    let key = roles_cache_key(&infraction.guild_id, &infraction.user_id);
+                    if let Err(e) = cache.set(&key, &roles, None).await {
+                        tracing::warn!(
+                            infraction_id = %infraction.uuid,
+                            error = ?e,
+                            "Failed to update cached member roles"
+                        );
+                    }
+
+                    tracing::info!(
+                        "Restored roles for quarantined user {} in guild {}",
+                        infraction.user_id,
+                        infraction.guild_id
+                    );
+                }
+            }
+        }
+
         if infraction.infraction_type == InfractionType::Ban {
             if let Err(e) = rest
                 .unban_member(
@@ -79,3 +121,27 @@ async fn expiry_job(rest: Arc<DiscordRestClient>, db: Arc<Database>) -> DiscordR
 
     Ok(())
 }
+
+/// Give an expired quarantine's roles back, keeping any managed roles the
+/// member holds now. Returns the member's new roles.
+async fn restore_roles(
+    rest: &DiscordRestClient,
+    infraction: &Infraction,
+    stored: &[Id],
+) -> DiscordResult<Vec<Id>> {
+    let (guild, member) = tokio::try_join!(
+        rest.get_guild(&infraction.guild_id),
+        rest.get_member(&infraction.guild_id, &infraction.user_id)
+    )?;
+    let roles = restored_roles(&guild, stored, &member.roles);
+
+    rest.modify_member_roles(
+        &infraction.guild_id,
+        &infraction.user_id,
+        &roles,
+        Some(Cow::Borrowed(REASON)),
+    )
+    .await?;
+
+    Ok(roles)
+}
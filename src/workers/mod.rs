@@ -1,6 +1,10 @@
 use std::sync::Arc;
 
-use bm_lib::{db::Database, discord::DiscordRestClient};
+use bm_lib::{
+    cache::{Cache, RedisCache},
+    db::Database,
+    discord::DiscordRestClient,
+};
 
 mod expiry;
 
@@ -8,10 +12,21 @@ pub struct Worker {
     pub interval: u64,
     pub db: Arc<Database>,
     pub rest: Arc<DiscordRestClient>,
+    pub cache: Arc<Cache<RedisCache>>,
 }
 
 impl Worker {
-    pub fn new(interval: u64, db: Arc<Database>, rest: Arc<DiscordRestClient>) -> Self {
-        Self { interval, db, rest }
+    pub fn new(
+        interval: u64,
+        db: Arc<Database>,
+        rest: Arc<DiscordRestClient>,
+        cache: Arc<Cache<RedisCache>>,
+    ) -> Self {
+        Self {
+            interval,
+            db,
+            rest,
+            cache,
+        }
     }
 }